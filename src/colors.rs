@@ -0,0 +1,105 @@
+use std::str::FromStr;
+use thiserror::Error;
+
+/// Colours of highlighted output, as ANSI SGR codes such as `0;32` for green or `1;34` for bold
+/// blue. Impls std::str::FromStr for the format of `$TOML_PATH_COLORS`, a colon separated list of
+/// codes in the order of the fields, such as `0;32:0;36:0;36:0;33:0;35:1;34:1;39`. Codes left out
+/// of the list or left empty, as in `::1;31`, keep their defaults
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Colors {
+    pub string: String,
+    pub integer: String,
+    pub float: String,
+    pub boolean: String,
+    pub datetime: String,
+    pub key: String,
+    /// `[table]` and `[[array-of-tables]]` headers
+    pub header: String,
+}
+
+impl Default for Colors {
+    fn default() -> Self {
+        Self {
+            string: String::from("0;32"),
+            integer: String::from("0;36"),
+            float: String::from("0;36"),
+            boolean: String::from("0;33"),
+            datetime: String::from("0;35"),
+            key: String::from("1;34"),
+            header: String::from("1;39"),
+        }
+    }
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ColorsError {
+    #[error("Invalid colour '{0}', expected an ANSI code such as '0;32'")]
+    InvalidCode(String),
+    #[error("Too many colours, expected at most 7: string, integer, float, boolean, datetime, key and header")]
+    TooMany,
+}
+
+impl FromStr for Colors {
+    type Err = ColorsError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let mut colors = Colors::default();
+        let mut fields = [
+            &mut colors.string,
+            &mut colors.integer,
+            &mut colors.float,
+            &mut colors.boolean,
+            &mut colors.datetime,
+            &mut colors.key,
+            &mut colors.header,
+        ];
+        let codes: Vec<&str> = s.split(':').collect();
+        if codes.len() > fields.len() {
+            return Err(ColorsError::TooMany);
+        }
+        for (field, code) in fields.iter_mut().zip(codes) {
+            if code.is_empty() {
+                continue;
+            }
+            if !code.chars().all(|c| c.is_ascii_digit() || c == ';') {
+                return Err(ColorsError::InvalidCode(code.to_string()));
+            }
+            **field = code.to_string();
+        }
+        Ok(colors)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    #[test]
+    fn test_colors_from_str() {
+        let expected = Colors {
+            string: String::from("1;31"),
+            integer: String::from("4"),
+            ..Colors::default()
+        };
+        assert_eq!(Ok(expected), Colors::from_str("1;31:4"));
+        let expected = Colors {
+            float: String::from("1;31"),
+            ..Colors::default()
+        };
+        assert_eq!(Ok(expected), Colors::from_str("::1;31"));
+        assert_eq!(Ok(Colors::default()), Colors::from_str(""));
+    }
+
+    #[test]
+    fn test_colors_from_str_invalid() {
+        let result = Colors::from_str("0;32:red");
+        assert_eq!(Err(ColorsError::InvalidCode(String::from("red"))), result);
+        let result = Colors::from_str("0;32:;3x:0;36");
+        assert_eq!(Err(ColorsError::InvalidCode(String::from(";3x"))), result);
+        assert_eq!(
+            Err(ColorsError::TooMany),
+            Colors::from_str("1:2:3:4:5:6:7:8")
+        );
+    }
+}
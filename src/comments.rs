@@ -0,0 +1,337 @@
+use crate::edit::{
+    child_mut, get_item_mut, prefix, resolved_steps, split_last, steps, suffix, Step,
+};
+use crate::toml_path::TomlPath;
+use eyre::bail;
+use eyre::Result;
+use toml::{Table, Value};
+use toml_edit::{Decor, DocumentMut, Item};
+
+/// Comments attached to a key or table, without their leading `#`
+#[derive(Debug, Default, PartialEq, Eq, Clone)]
+pub struct Comments {
+    /// Lines of comments directly above the key or table
+    pub leading: Vec<String>,
+
+    /// Comment at the end of the line of the key or table header
+    pub trailing: Option<String>,
+}
+
+fn comment_text(line: &str) -> Option<String> {
+    line.trim()
+        .strip_prefix('#')
+        .map(|comment| comment.trim().to_string())
+}
+
+impl Comments {
+    fn from_decor(prefix: &str, suffix: &str) -> Self {
+        Self {
+            leading: prefix.lines().filter_map(comment_text).collect(),
+            trailing: comment_text(suffix),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.leading.is_empty() && self.trailing.is_none()
+    }
+
+    /// The comments as a table, such as `{ leading = ["DO NOT BUMP"], trailing = "pinned" }`.
+    /// The trailing comment is left out when there is none
+    pub fn to_value(&self) -> Value {
+        let mut table = Table::new();
+        let leading = self.leading.iter().cloned().map(Value::String).collect();
+        table.insert(String::from("leading"), Value::Array(leading));
+        if let Some(trailing) = &self.trailing {
+            table.insert(String::from("trailing"), Value::String(trailing.clone()));
+        }
+        Value::Table(table)
+    }
+}
+
+fn push(found: &mut Vec<(TomlPath, Comments)>, path: TomlPath, comments: Comments) {
+    if !comments.is_empty() {
+        found.push((path, comments));
+    }
+}
+
+fn collect(table: &toml_edit::Table, path: &TomlPath, found: &mut Vec<(TomlPath, Comments)>) {
+    for (key, item) in table.iter() {
+        let path = path.join_key(key);
+        let key_prefix = table
+            .key(key)
+            .map(|key| prefix(key.leaf_decor()))
+            .unwrap_or_default();
+        match item {
+            Item::Value(value) => {
+                let comments = Comments::from_decor(&key_prefix, &suffix(value.decor()));
+                push(found, path, comments);
+            }
+            Item::Table(table) if table.is_dotted() => {
+                push(found, path.clone(), Comments::from_decor(&key_prefix, ""));
+                collect(table, &path, found);
+            }
+            Item::Table(table) => {
+                let decor = table.decor();
+                let comments = Comments::from_decor(&prefix(decor), &suffix(decor));
+                push(found, path.clone(), comments);
+                collect(table, &path, found);
+            }
+            Item::ArrayOfTables(array) => {
+                for (i, table) in array.iter().enumerate() {
+                    let path = path.join_index(i);
+                    let decor = table.decor();
+                    let comments = Comments::from_decor(&prefix(decor), &suffix(decor));
+                    push(found, path.clone(), comments);
+                    collect(table, &path, found);
+                }
+            }
+            Item::None => {}
+        }
+    }
+}
+
+/// Every key and table with comments at or inside of a path, in the order of the document
+pub fn comments(doc: &DocumentMut, path: &TomlPath) -> Result<Vec<(TomlPath, Comments)>> {
+    let target = resolved_steps(doc, path)?;
+    let mut found = Vec::new();
+    collect(doc.as_table(), &TomlPath::root(), &mut found);
+    let mut inside = Vec::new();
+    for (path, comments) in found {
+        if steps(&path)?.starts_with(&target) {
+            inside.push((path, comments));
+        }
+    }
+    Ok(inside)
+}
+
+/// Comments attached to the key or table at a path
+fn comments_at(doc: &DocumentMut, path: &TomlPath) -> Result<Comments> {
+    let target = resolved_steps(doc, path)?;
+    for (found, comments) in comments(doc, path)? {
+        if steps(&found)? == target {
+            return Ok(comments);
+        }
+    }
+    Ok(Comments::default())
+}
+
+/// Replace the comment lines of a prefix, keeping the blank lines before them and the indentation
+/// of the item itself
+fn set_leading(decor: &mut Decor, default: &str, leading: &[String]) {
+    let raw = decor.prefix().map(|_| prefix(decor));
+    let raw = raw.as_deref().unwrap_or(default);
+    let (lines, indent) = match raw.rsplit_once('\n') {
+        Some((lines, indent)) => (lines.split('\n').collect(), indent),
+        None => (Vec::new(), raw),
+    };
+    let mut prefix = String::new();
+    for _ in lines.iter().take_while(|line| line.trim().is_empty()) {
+        prefix.push('\n');
+    }
+    for line in leading {
+        prefix.push_str(format!("# {}", line).trim_end());
+        prefix.push('\n');
+    }
+    prefix.push_str(indent);
+    decor.set_prefix(prefix);
+}
+
+fn set_trailing(decor: &mut Decor, trailing: &Option<String>) {
+    match trailing {
+        Some(trailing) => decor.set_suffix(format!(" # {}", trailing).trim_end().to_string()),
+        None => decor.set_suffix(""),
+    }
+}
+
+/// Replace the comments of a key or table. Empty comments are removed
+pub fn set_comments(doc: &mut DocumentMut, path: &TomlPath, comments: &Comments) -> Result<()> {
+    if let Some(trailing) = &comments.trailing {
+        if trailing.contains('\n') {
+            bail!("Trailing comment at '{}' must be a single line", path);
+        }
+    }
+    let (parents, last) = split_last(path)?;
+    let parent = get_item_mut(doc, &parents, path)?;
+    match (parent, &last) {
+        (Item::Table(table), Step::Key(key)) => {
+            let Some((mut key, item)) = table.get_key_value_mut(key) else {
+                bail!("Nothing found at '{}'", path);
+            };
+            match item {
+                Item::Value(value) => {
+                    set_leading(key.leaf_decor_mut(), "", &comments.leading);
+                    set_trailing(value.decor_mut(), &comments.trailing);
+                }
+                Item::Table(table) if table.is_dotted() => {
+                    if comments.trailing.is_some() {
+                        bail!(
+                            "Dotted key '{}' has no line of its own for a trailing comment",
+                            path
+                        );
+                    }
+                    set_leading(key.leaf_decor_mut(), "", &comments.leading);
+                }
+                Item::Table(table) => {
+                    set_leading(table.decor_mut(), "\n", &comments.leading);
+                    set_trailing(table.decor_mut(), &comments.trailing);
+                }
+                _ => bail!(
+                    "Comments cannot be attached to the array of tables at '{}', only its entries",
+                    path
+                ),
+            }
+        }
+        (parent @ Item::ArrayOfTables(_), Step::Index(_)) => {
+            let Some(Item::Table(table)) = child_mut(parent, &last) else {
+                bail!("Nothing found at '{}'", path);
+            };
+            set_leading(table.decor_mut(), "\n", &comments.leading);
+            set_trailing(table.decor_mut(), &comments.trailing);
+        }
+        _ => bail!(
+            "Comments can only be attached to keys and tables, not '{}'",
+            path
+        ),
+    }
+    Ok(())
+}
+
+/// Set the trailing comment of a key or table, keeping the comments above it.
+/// An empty comment removes it
+pub(crate) fn set_trailing_comment(
+    doc: &mut DocumentMut,
+    path: &TomlPath,
+    comment: &str,
+) -> Result<()> {
+    let mut comments = comments_at(doc, path)?;
+    comments.trailing = Some(comment.to_string()).filter(|comment| !comment.is_empty());
+    set_comments(doc, path, &comments)
+}
+
+/// Set the comment lines above a key or table, keeping its trailing comment.
+/// An empty comment removes them
+pub(crate) fn set_leading_comment(
+    doc: &mut DocumentMut,
+    path: &TomlPath,
+    comment: &str,
+) -> Result<()> {
+    let mut comments = comments_at(doc, path)?;
+    comments.leading = comment.lines().map(str::to_string).collect();
+    set_comments(doc, path, &comments)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const DOC: &str = r#"# The package
+[package] # main
+name = "app"
+
+# DO NOT BUMP
+#see #12
+version = "0.1.0" # TODO: release
+
+[[bin]]
+name = "app"
+"#;
+
+    fn path(s: &str) -> TomlPath {
+        TomlPath::from_str(s).unwrap()
+    }
+
+    fn comments_of(s: &str) -> Comments {
+        let doc = DocumentMut::from_str(DOC).unwrap();
+        comments_at(&doc, &path(s)).unwrap()
+    }
+
+    #[test]
+    fn test_comments() {
+        let doc = DocumentMut::from_str(DOC).unwrap();
+        let expected = vec![
+            (
+                path(".package"),
+                Comments {
+                    leading: vec![String::from("The package")],
+                    trailing: Some(String::from("main")),
+                },
+            ),
+            (
+                path(".package.version"),
+                Comments {
+                    leading: vec![String::from("DO NOT BUMP"), String::from("see #12")],
+                    trailing: Some(String::from("TODO: release")),
+                },
+            ),
+        ];
+        assert_eq!(expected, comments(&doc, &path(".")).unwrap());
+        assert_eq!(
+            expected[1..],
+            comments(&doc, &path(".package.version")).unwrap()
+        );
+        assert_eq!(Comments::default(), comments_of(".package.name"));
+        assert_eq!(Comments::default(), comments_of(".bin[0]"));
+    }
+
+    fn edit(f: impl Fn(&mut DocumentMut) -> Result<()>) -> Result<String> {
+        let mut doc = DocumentMut::from_str(DOC).unwrap();
+        f(&mut doc)?;
+        Ok(doc.to_string())
+    }
+
+    #[test]
+    fn test_set_comments() {
+        let result = edit(|doc| {
+            set_trailing_comment(doc, &path(".package.name"), "the name")?;
+            set_leading_comment(doc, &path(".package.version"), "pinned\nby ops")?;
+            set_leading_comment(doc, &path(".bin[-1]"), "binaries")
+        });
+        let expected = DOC
+            .replace("name = \"app\"\n\n", "name = \"app\" # the name\n\n")
+            .replace("# DO NOT BUMP\n#see #12\n", "# pinned\n# by ops\n")
+            .replace("\n[[bin]]", "\n# binaries\n[[bin]]");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_negative_index() {
+        let source = "[[bin]]\nname = \"a\"\n\n[[bin]]\n# lead\nname = \"b\" # second\n";
+        let mut doc = DocumentMut::from_str(source).unwrap();
+        let expected = Comments {
+            leading: vec![String::from("lead")],
+            trailing: Some(String::from("second")),
+        };
+        let found = vec![(path(".bin[1].name"), expected.clone())];
+        assert_eq!(found, comments(&doc, &path(".bin[-1]")).unwrap());
+        assert_eq!(expected, comments_at(&doc, &path(".bin[-1].name")).unwrap());
+        set_leading_comment(&mut doc, &path(".bin[-1].name"), "hey").unwrap();
+        assert_eq!(source.replace("# lead", "# hey"), doc.to_string());
+    }
+
+    #[test]
+    fn test_clear_comments() {
+        let result = edit(|doc| {
+            set_comments(doc, &path(".package"), &Comments::default())?;
+            set_comments(doc, &path(".package.version"), &Comments::default())
+        });
+        let expected = DOC
+            .replace("# The package\n[package] # main", "[package]")
+            .replace("\n# DO NOT BUMP\n#see #12\n", "\n")
+            .replace(" # TODO: release", "");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_set_comments_errors() {
+        let trailing = Comments {
+            leading: Vec::new(),
+            trailing: Some(String::from("two\nlines")),
+        };
+        assert!(edit(|doc| set_comments(doc, &path(".package.name"), &trailing)).is_err());
+        let comments = Comments::default();
+        assert!(edit(|doc| set_comments(doc, &path(".bin"), &comments)).is_err());
+        assert!(edit(|doc| set_comments(doc, &path(".package.missing"), &comments)).is_err());
+    }
+}
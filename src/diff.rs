@@ -0,0 +1,323 @@
+use crate::edit::{get_item, to_value};
+use crate::format::to_json;
+use crate::toml_path::TomlPath;
+use eyre::Result;
+use serde_json::json;
+use std::fmt;
+use toml::Value;
+use toml_edit::{DocumentMut, Item};
+
+/// Options for [diff]
+#[derive(Debug, Default, Clone)]
+pub struct DiffOptions {
+    /// Report tables that have the same keys in a different order
+    pub key_order: bool,
+
+    /// Report values that are equal but written differently, such as `0xff` and `255` or `'a'` and `"a"`
+    pub formatting: bool,
+}
+
+/// A single difference between two tomls
+#[derive(Debug, PartialEq, Clone)]
+pub enum Change {
+    /// Value only present in the new toml
+    Added { path: TomlPath, value: Value },
+
+    /// Value only present in the old toml
+    Removed { path: TomlPath, value: Value },
+
+    /// Value present in both tomls, with different contents
+    Changed {
+        path: TomlPath,
+        old: Value,
+        new: Value,
+    },
+
+    /// Table with the same keys, in a different order.
+    /// Only reported with [DiffOptions::key_order]
+    Reordered {
+        path: TomlPath,
+        old: Vec<String>,
+        new: Vec<String>,
+    },
+
+    /// Equal values, written differently.
+    /// Only reported with [DiffOptions::formatting]
+    Reformatted {
+        path: TomlPath,
+        old: String,
+        new: String,
+    },
+}
+
+impl Change {
+    /// Path of the changed value
+    pub fn path(&self) -> &TomlPath {
+        match self {
+            Change::Added { path, .. }
+            | Change::Removed { path, .. }
+            | Change::Changed { path, .. }
+            | Change::Reordered { path, .. }
+            | Change::Reformatted { path, .. } => path,
+        }
+    }
+
+    /// Json object describing the change
+    pub fn to_json(&self) -> serde_json::Value {
+        let path = self.path().to_string();
+        match self {
+            Change::Added { value, .. } => {
+                json!({"change": "added", "path": path, "value": to_json(value)})
+            }
+            Change::Removed { value, .. } => {
+                json!({"change": "removed", "path": path, "value": to_json(value)})
+            }
+            Change::Changed { old, new, .. } => {
+                json!({"change": "changed", "path": path, "old": to_json(old), "new": to_json(new)})
+            }
+            Change::Reordered { old, new, .. } => {
+                json!({"change": "reordered", "path": path, "old": old, "new": new})
+            }
+            Change::Reformatted { old, new, .. } => {
+                json!({"change": "reformatted", "path": path, "old": old, "new": new})
+            }
+        }
+    }
+}
+
+fn format_keys(keys: &[String]) -> String {
+    let keys = Value::Array(keys.iter().cloned().map(Value::String).collect());
+    keys.to_string()
+}
+
+/// One line per change, prefixed with '+' (added), '-' (removed), '~' (changed),
+/// '^' (reordered) or '=' (reformatted)
+impl fmt::Display for Change {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Change::Added { path, value } => write!(f, "+ {} {}", path, value),
+            Change::Removed { path, value } => write!(f, "- {} {}", path, value),
+            Change::Changed { path, old, new } => write!(f, "~ {} {} -> {}", path, old, new),
+            Change::Reordered { path, old, new } => {
+                write!(f, "^ {} {} -> {}", path, format_keys(old), format_keys(new))
+            }
+            Change::Reformatted { path, old, new } => write!(f, "= {} {} -> {}", path, old, new),
+        }
+    }
+}
+
+/// Source representation of a scalar value, such as `0xff` or `'literal'`
+fn repr(item: &Item) -> Option<String> {
+    let repr = match item.as_value()? {
+        toml_edit::Value::String(string) => string.display_repr(),
+        toml_edit::Value::Integer(int) => int.display_repr(),
+        toml_edit::Value::Float(float) => float.display_repr(),
+        toml_edit::Value::Boolean(bool) => bool.display_repr(),
+        toml_edit::Value::Datetime(date) => date.display_repr(),
+        toml_edit::Value::Array(_) | toml_edit::Value::InlineTable(_) => return None,
+    };
+    Some(repr.into_owned())
+}
+
+struct Differ<'a> {
+    old_doc: &'a DocumentMut,
+    new_doc: &'a DocumentMut,
+    options: &'a DiffOptions,
+    changes: Vec<Change>,
+}
+
+impl Differ<'_> {
+    fn diff_tables(&mut self, old: &toml::Table, new: &toml::Table, path: &TomlPath) {
+        if self.options.key_order {
+            let old_keys: Vec<String> = old
+                .keys()
+                .filter(|k| new.contains_key(*k))
+                .cloned()
+                .collect();
+            let new_keys: Vec<String> = new
+                .keys()
+                .filter(|k| old.contains_key(*k))
+                .cloned()
+                .collect();
+            if old_keys != new_keys {
+                self.changes.push(Change::Reordered {
+                    path: path.clone(),
+                    old: old_keys,
+                    new: new_keys,
+                });
+            }
+        }
+        for (key, old_value) in old {
+            let path = path.join_key(key);
+            match new.get(key) {
+                Some(new_value) => self.diff_values(old_value, new_value, &path),
+                None => self.changes.push(Change::Removed {
+                    path,
+                    value: old_value.clone(),
+                }),
+            }
+        }
+        for (key, new_value) in new {
+            if !old.contains_key(key) {
+                self.changes.push(Change::Added {
+                    path: path.join_key(key),
+                    value: new_value.clone(),
+                });
+            }
+        }
+    }
+
+    fn diff_arrays(&mut self, old: &[Value], new: &[Value], path: &TomlPath) {
+        for (i, old_value) in old.iter().enumerate() {
+            let path = path.join_index(i);
+            match new.get(i) {
+                Some(new_value) => self.diff_values(old_value, new_value, &path),
+                None => self.changes.push(Change::Removed {
+                    path,
+                    value: old_value.clone(),
+                }),
+            }
+        }
+        for (i, new_value) in new.iter().enumerate().skip(old.len()) {
+            self.changes.push(Change::Added {
+                path: path.join_index(i),
+                value: new_value.clone(),
+            });
+        }
+    }
+
+    fn diff_reprs(&mut self, path: &TomlPath) {
+        let old = get_item(self.old_doc, path).ok().and_then(repr);
+        let new = get_item(self.new_doc, path).ok().and_then(repr);
+        if let (Some(old), Some(new)) = (old, new) {
+            if old != new {
+                self.changes.push(Change::Reformatted {
+                    path: path.clone(),
+                    old,
+                    new,
+                });
+            }
+        }
+    }
+
+    fn diff_values(&mut self, old: &Value, new: &Value, path: &TomlPath) {
+        match (old, new) {
+            (Value::Table(old), Value::Table(new)) => self.diff_tables(old, new, path),
+            (Value::Array(old), Value::Array(new)) => self.diff_arrays(old, new, path),
+            (old, new) if same(old, new) => {
+                if self.options.formatting {
+                    self.diff_reprs(path);
+                }
+            }
+            (old, new) => self.changes.push(Change::Changed {
+                path: path.clone(),
+                old: old.clone(),
+                new: new.clone(),
+            }),
+        }
+    }
+}
+
+/// Whether two values other than tables and arrays are equal, where nan equals nan
+fn same(old: &Value, new: &Value) -> bool {
+    match (old, new) {
+        (Value::Float(old), Value::Float(new)) => old.is_nan() && new.is_nan() || old == new,
+        (old, new) => old == new,
+    }
+}
+
+/// Structural differences between two toml documents, labelled by toml path.
+/// Key order and formatting are ignored unless enabled in the [DiffOptions]
+pub fn diff(old: &DocumentMut, new: &DocumentMut, options: &DiffOptions) -> Result<Vec<Change>> {
+    let mut differ = Differ {
+        old_doc: old,
+        new_doc: new,
+        options,
+        changes: Vec::new(),
+    };
+    differ.diff_values(&to_value(old), &to_value(new), &TomlPath::root());
+    Ok(differ.changes)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    fn diff_strs(old: &str, new: &str, options: &DiffOptions) -> Vec<String> {
+        let old = DocumentMut::from_str(old).unwrap();
+        let new = DocumentMut::from_str(new).unwrap();
+        let changes = diff(&old, &new, options).unwrap();
+        changes.iter().map(|change| change.to_string()).collect()
+    }
+
+    #[test]
+    fn test_diff_changed() {
+        let old = "[dependencies]\nserde = { version = \"1.0.200\" }";
+        let new = "[dependencies]\nserde = { version = \"1.0.210\" }";
+        let expected = vec![r#"~ .dependencies.serde.version "1.0.200" -> "1.0.210""#];
+        let result = diff_strs(old, new, &DiffOptions::default());
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_diff_added_removed() {
+        let old = "a = 1\nb = [1, 2]";
+        let new = "b = [1]\nc = 'x'";
+        let expected = vec!["- .a 1", "- .b[1] 2", r#"+ .c "x""#];
+        let result = diff_strs(old, new, &DiffOptions::default());
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_diff_nan() {
+        let old = "x = nan\ny = [1.0, nan]\nz = nan";
+        let new = "x = nan\ny = [1.0, nan]\nz = 1.0";
+        let expected = vec!["~ .z nan -> 1.0"];
+        assert_eq!(expected, diff_strs(old, new, &DiffOptions::default()));
+    }
+
+    #[test]
+    fn test_diff_quoted_keys() {
+        let old = "[package.metadata]\n\"docs.rs\" = 1";
+        let new = "[package.metadata]\n\"docs.rs\" = 2";
+        let expected = vec![r#"~ .package.metadata["docs.rs"] 1 -> 2"#];
+        let result = diff_strs(old, new, &DiffOptions::default());
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_diff_ignores_order_and_formatting() {
+        let old = "[a]\nx = 255\ny = 'z'";
+        let new = "a = { y = \"z\", x = 0xff }";
+        let result = diff_strs(old, new, &DiffOptions::default());
+        assert_eq!(Vec::<String>::new(), result);
+    }
+
+    #[test]
+    fn test_diff_key_order() {
+        let old = "[a]\nx = 1\ny = 2";
+        let new = "[a]\ny = 2\nx = 1";
+        let options = DiffOptions {
+            key_order: true,
+            ..Default::default()
+        };
+        let expected = vec![r#"^ .a ["x", "y"] -> ["y", "x"]"#];
+        let result = diff_strs(old, new, &options);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_diff_formatting() {
+        let old = "[a]\nx = 255\ny = 'z'";
+        let new = "[a]\nx = 0xff\ny = \"z\"";
+        let options = DiffOptions {
+            formatting: true,
+            ..Default::default()
+        };
+        let expected = vec!["= .a.x 255 -> 0xff", r#"= .a.y 'z' -> "z""#];
+        let result = diff_strs(old, new, &options);
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,993 @@
+use crate::toml_path::{Index, Op, TomlPath};
+use eyre::Result;
+use eyre::{bail, eyre};
+use serde::Deserialize;
+use toml_edit::{
+    Array, ArrayOfTables, Decor, DocumentMut, InlineTable, Item, Key, RawString, Table,
+};
+
+/// One step from an item to one of its children
+#[derive(Debug, PartialEq)]
+pub(crate) enum Step<'a> {
+    Key(&'a str),
+    Index(isize),
+}
+
+/// Break a path down into steps. Edits address a single location, so only paths without
+/// multiple names, multiple indexes or ranges are supported
+pub(crate) fn steps(path: &TomlPath) -> Result<Vec<Step<'_>>> {
+    let mut steps = Vec::new();
+    for part in path.parts() {
+        match part {
+            Op::Dot => {}
+            Op::Name(name) => steps.push(Step::Key(name)),
+            Op::BracketName(names) if names.len() == 1 => steps.push(Step::Key(&names[0])),
+            Op::BracketIndex(indexes) if indexes.len() == 1 => match indexes[0] {
+                Index::Number(i) => steps.push(Step::Index(i)),
+                Index::Range(_) => bail!("Path '{}' does not point to a single location", path),
+            },
+            _ => bail!("Path '{}' does not point to a single location", path),
+        }
+    }
+    Ok(steps)
+}
+
+/// Negative indexes count back from the end of the array, where '-1' is the final item
+fn resolve_index(index: isize, len: usize) -> Option<usize> {
+    if index < 0 {
+        len.checked_sub(index.unsigned_abs())
+    } else {
+        Some(index as usize)
+    }
+}
+
+fn array_len(item: &Item) -> Option<usize> {
+    match item {
+        Item::ArrayOfTables(array) => Some(array.len()),
+        Item::Value(toml_edit::Value::Array(array)) => Some(array.len()),
+        _ => None,
+    }
+}
+
+fn child<'a>(item: &'a Item, step: &Step) -> Option<&'a Item> {
+    match step {
+        Step::Key(key) => item.get(key),
+        Step::Index(index) => item.get(resolve_index(*index, array_len(item)?)?),
+    }
+}
+
+pub(crate) fn child_mut<'a>(item: &'a mut Item, step: &Step) -> Option<&'a mut Item> {
+    match step {
+        Step::Key(key) => item.get_mut(key),
+        Step::Index(index) => {
+            let index = resolve_index(*index, array_len(item)?)?;
+            item.get_mut(index)
+        }
+    }
+}
+
+fn table_to_value<'a>(items: impl Iterator<Item = (&'a str, &'a Item)>) -> toml::Value {
+    let table: toml::Table = items
+        .filter_map(|(key, item)| Some((key.to_string(), item_to_value(item)?)))
+        .collect();
+    toml::Value::Table(table)
+}
+
+pub(crate) fn edit_value_to_value(value: &toml_edit::Value) -> toml::Value {
+    match value {
+        toml_edit::Value::String(string) => toml::Value::String(string.value().clone()),
+        toml_edit::Value::Integer(int) => toml::Value::Integer(*int.value()),
+        toml_edit::Value::Float(float) => toml::Value::Float(*float.value()),
+        toml_edit::Value::Boolean(bool) => toml::Value::Boolean(*bool.value()),
+        toml_edit::Value::Datetime(date) => toml::Value::Datetime(*date.value()),
+        toml_edit::Value::Array(array) => {
+            toml::Value::Array(array.iter().map(edit_value_to_value).collect())
+        }
+        toml_edit::Value::InlineTable(table) => toml::Value::Table(
+            table
+                .iter()
+                .map(|(key, value)| (key.to_string(), edit_value_to_value(value)))
+                .collect(),
+        ),
+    }
+}
+
+/// Convert an item of a format preserving document to a plain toml value.
+/// Returns None for [Item::None]
+pub(crate) fn item_to_value(item: &Item) -> Option<toml::Value> {
+    match item {
+        Item::None => None,
+        Item::Value(value) => Some(edit_value_to_value(value)),
+        Item::Table(table) => Some(table_to_value(table.iter())),
+        Item::ArrayOfTables(array) => Some(toml::Value::Array(
+            array
+                .iter()
+                .map(|table| table_to_value(table.iter()))
+                .collect(),
+        )),
+    }
+}
+
+/// How tables and arrays of tables are written when they are inserted into a document
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum TableStyle {
+    /// `[[header]]` sections for arrays of tables. `[header]` sections for top level tables and
+    /// tables containing other tables, inline tables otherwise
+    #[default]
+    Auto,
+    /// `[header]` and `[[header]]` sections
+    Header,
+    /// Dotted keys, such as `docs.rs.all-features = true`. Arrays of tables are written inline
+    Dotted,
+    /// Inline tables and arrays of inline tables
+    Inline,
+}
+
+/// Whether a value is a non-empty array holding only tables
+pub(crate) fn is_array_of_tables(value: &toml::Value) -> bool {
+    match value {
+        toml::Value::Array(array) => !array.is_empty() && array.iter().all(toml::Value::is_table),
+        _ => false,
+    }
+}
+
+/// Convert a plain toml value to an item that can be inserted at a path of a format preserving
+/// document, writing its tables in the given style
+pub(crate) fn value_to_item(
+    path: &TomlPath,
+    value: &toml::Value,
+    style: TableStyle,
+) -> Result<Item> {
+    let top_level = steps(path)?.len() == 1;
+    Ok(styled_item(value, style, top_level))
+}
+
+fn styled_item(value: &toml::Value, style: TableStyle, top_level: bool) -> Item {
+    if let (TableStyle::Dotted, toml::Value::Table(table)) = (style, value) {
+        if !table.is_empty() {
+            let mut dotted = Table::new();
+            for (key, value) in table {
+                dotted.insert(key, styled_item(value, style, false));
+            }
+            dotted.set_dotted(true);
+            return Item::Table(dotted);
+        }
+    }
+    let header = match (style, value) {
+        (TableStyle::Inline | TableStyle::Dotted, _) => false,
+        (TableStyle::Header, _) => true,
+        (TableStyle::Auto, toml::Value::Table(table)) => {
+            top_level
+                || table
+                    .values()
+                    .any(|value| value.is_table() || is_array_of_tables(value))
+        }
+        (TableStyle::Auto, _) => true,
+    };
+    match value {
+        toml::Value::Table(table) if header => Item::Table(styled_table(table, style)),
+        toml::Value::Array(array) if header && is_array_of_tables(value) => {
+            let mut tables = ArrayOfTables::new();
+            for table in array.iter().filter_map(toml::Value::as_table) {
+                tables.push(styled_table(table, style));
+            }
+            Item::ArrayOfTables(tables)
+        }
+        _ => Item::Value(value_to_edit_value(value)),
+    }
+}
+
+fn styled_table(table: &toml::Table, style: TableStyle) -> Table {
+    let mut styled = Table::new();
+    for (key, value) in table {
+        styled.insert(key, styled_item(value, style, false));
+    }
+    // Tables holding only other tables, as sections of their own, don't need a header. Empty
+    // tables keep theirs, or they would disappear
+    let only_tables = styled
+        .iter()
+        .all(|(_, item)| item.is_table() || item.is_array_of_tables());
+    styled.set_implicit(!styled.is_empty() && only_tables);
+    styled
+}
+
+pub(crate) fn value_to_edit_value(value: &toml::Value) -> toml_edit::Value {
+    match value {
+        toml::Value::String(string) => toml_edit::Value::from(string.clone()),
+        toml::Value::Integer(int) => toml_edit::Value::from(*int),
+        toml::Value::Float(float) => toml_edit::Value::from(*float),
+        toml::Value::Boolean(bool) => toml_edit::Value::from(*bool),
+        toml::Value::Datetime(date) => toml_edit::Value::from(*date),
+        toml::Value::Array(array) => {
+            toml_edit::Value::Array(array.iter().map(value_to_edit_value).collect::<Array>())
+        }
+        toml::Value::Table(table) => toml_edit::Value::InlineTable(
+            table
+                .iter()
+                .map(|(k, v)| (k.clone(), value_to_edit_value(v)))
+                .collect::<InlineTable>(),
+        ),
+    }
+}
+
+pub(crate) fn raw_str(raw: &RawString) -> String {
+    raw.as_str().unwrap_or_default().to_string()
+}
+
+/// Whitespace and comments before an item
+pub(crate) fn prefix(decor: &Decor) -> String {
+    decor.prefix().map(raw_str).unwrap_or_default()
+}
+
+/// Whitespace and comments after an item
+pub(crate) fn suffix(decor: &Decor) -> String {
+    decor.suffix().map(raw_str).unwrap_or_default()
+}
+
+/// Convert a format preserving document to a plain toml value
+pub(crate) fn to_value(doc: &DocumentMut) -> toml::Value {
+    table_to_value(doc.iter())
+}
+
+/// Get the item at a path in a toml document
+pub(crate) fn get_item<'a>(doc: &'a DocumentMut, path: &TomlPath) -> Result<&'a Item> {
+    let mut item = doc.as_item();
+    for step in steps(path)? {
+        let Some(next) = child(item, &step) else {
+            bail!("Nothing found at '{}'", path);
+        };
+        item = next;
+    }
+    Ok(item)
+}
+
+/// The steps of a path to an item of the document, with negative indexes replaced by the
+/// index they count back to
+pub(crate) fn resolved_steps<'a>(doc: &DocumentMut, path: &'a TomlPath) -> Result<Vec<Step<'a>>> {
+    let mut item = doc.as_item();
+    let mut resolved = Vec::new();
+    for step in steps(path)? {
+        let Some(next) = child(item, &step) else {
+            bail!("Nothing found at '{}'", path);
+        };
+        let step = match step {
+            Step::Index(index) if index < 0 => {
+                let len = array_len(item).unwrap_or_default();
+                Step::Index((len - index.unsigned_abs()) as isize)
+            }
+            step => step,
+        };
+        resolved.push(step);
+        item = next;
+    }
+    Ok(resolved)
+}
+
+/// Split a path into the steps to its parent, and the final step from the parent
+pub(crate) fn split_last(path: &TomlPath) -> Result<(Vec<Step<'_>>, Step<'_>)> {
+    let mut steps = steps(path)?;
+    let Some(last) = steps.pop() else {
+        bail!("Path '{}' points to the root of the document", path);
+    };
+    Ok((steps, last))
+}
+
+pub(crate) fn get_item_mut<'a>(
+    doc: &'a mut DocumentMut,
+    steps: &[Step],
+    path: &TomlPath,
+) -> Result<&'a mut Item> {
+    let mut item = doc.as_item_mut();
+    for step in steps {
+        let Some(next) = child_mut(item, step) else {
+            bail!("Nothing found at '{}'", path);
+        };
+        item = next;
+    }
+    Ok(item)
+}
+
+/// Get the item at a path in a toml document for editing
+pub(crate) fn get_item_mut_at<'a>(
+    doc: &'a mut DocumentMut,
+    path: &TomlPath,
+) -> Result<&'a mut Item> {
+    let steps = steps(path)?;
+    get_item_mut(doc, &steps, path)
+}
+
+fn into_value(item: Item) -> Result<toml_edit::Value> {
+    item.into_value()
+        .map_err(|item| eyre!("Cannot use {} as a value", item.type_name()))
+}
+
+fn into_table(item: Item) -> Result<Table> {
+    item.into_table()
+        .map_err(|item| eyre!("Cannot use {} as a table", item.type_name()))
+}
+
+/// Convert an item to a representation its parent can hold.
+/// For example, tables nested in inline tables or arrays must themselves be inline
+fn fit(parent: &Item, item: Item) -> Result<Item> {
+    match parent {
+        Item::Value(_) => Ok(Item::Value(into_value(item)?)),
+        Item::ArrayOfTables(_) => Ok(Item::Table(into_table(item)?)),
+        Item::Table(_) | Item::None => Ok(item),
+    }
+}
+
+fn insert_table(array: &mut ArrayOfTables, index: usize, table: Table) {
+    let mut tables: Vec<Table> = array.iter().cloned().collect();
+    if let (0, Some(first)) = (index, tables.first_mut()) {
+        // The old first table may sit at the start of the document without a blank line before it
+        let prefix = first.decor().prefix().and_then(|prefix| prefix.as_str());
+        if prefix.is_none_or(str::is_empty) {
+            first.decor_mut().set_prefix("\n");
+        }
+    }
+    tables.insert(index, table);
+    array.clear();
+    for table in tables {
+        array.push(table);
+    }
+}
+
+/// Replace the existing item at a path.
+/// The key and value keep their decor, such as comments and whitespace
+pub(crate) fn replace_item(doc: &mut DocumentMut, path: &TomlPath, item: Item) -> Result<Item> {
+    let (parents, last) = split_last(path)?;
+    let parent = get_item_mut(doc, &parents, path)?;
+    replace_child(parent, &last, item, path)
+}
+
+fn replace_child(parent: &mut Item, last: &Step, item: Item, path: &TomlPath) -> Result<Item> {
+    let mut item = fit(parent, item)?;
+    let Some(existing) = child_mut(parent, last) else {
+        bail!("Nothing found at '{}'", path);
+    };
+    if let (Item::Value(old), Item::Value(new)) = (&*existing, &mut item) {
+        *new.decor_mut() = old.decor().clone();
+    }
+    Ok(std::mem::replace(existing, item))
+}
+
+/// Insert an item at a path. The parent of the path must already exist.
+/// An existing value at a key is replaced, while inserting into an array shifts later items back.
+/// Array indexes may point one past the final item to append. Negative indexes count back from the end
+/// of the array after the insertion, so '-1' also appends
+pub(crate) fn insert_item(doc: &mut DocumentMut, path: &TomlPath, item: Item) -> Result<()> {
+    let (parents, last) = split_last(path)?;
+    let parent = get_item_mut(doc, &parents, path)?;
+    insert_child(parent, &last, item, path)
+}
+
+fn insert_child(parent: &mut Item, last: &Step, item: Item, path: &TomlPath) -> Result<()> {
+    match *last {
+        Step::Key(key) => {
+            if child(parent, last).is_some() {
+                replace_child(parent, last, item, path)?;
+                return Ok(());
+            }
+            let item = fit(parent, item)?;
+            match parent {
+                Item::Table(table) => {
+                    table.insert(key, item);
+                }
+                Item::Value(toml_edit::Value::InlineTable(table)) => {
+                    let mut value = into_value(item)?;
+                    // The space before the closing brace moves to the new final value
+                    if let Some((_, last)) = table.iter_mut().last() {
+                        if let Some(suffix) = last.decor().suffix().cloned() {
+                            last.decor_mut().set_suffix("");
+                            value.decor_mut().set_suffix(suffix);
+                        }
+                    }
+                    table.insert(key, value);
+                }
+                _ => bail!(
+                    "Cannot insert a key into {} at '{}'",
+                    parent.type_name(),
+                    path
+                ),
+            }
+        }
+        Step::Index(index) => {
+            let Some(len) = array_len(parent) else {
+                bail!(
+                    "Cannot insert an index into {} at '{}'",
+                    parent.type_name(),
+                    path
+                );
+            };
+            let Some(index) = resolve_index(index, len + 1).filter(|i| *i <= len) else {
+                bail!("Index out of bounds at '{}'", path);
+            };
+            let item = fit(parent, item)?;
+            match parent {
+                Item::Value(toml_edit::Value::Array(array)) => {
+                    array.insert(index, into_value(item)?)
+                }
+                Item::ArrayOfTables(array) => insert_table(array, index, into_table(item)?),
+                _ => unreachable!("array_len only accepts arrays"),
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Whether a document has any `[header]` or `[[header]]` sections
+fn has_sections(doc: &DocumentMut) -> bool {
+    doc.iter().any(|(_, item)| match item {
+        Item::Table(table) => !table.is_dotted(),
+        Item::ArrayOfTables(_) => true,
+        Item::Value(_) | Item::None => false,
+    })
+}
+
+/// Narrow a style down to one a parent can hold. Headers cannot appear inside of dotted or inline
+/// tables, and inline tables can only hold other inline tables
+fn fit_style(parent: &Item, style: TableStyle) -> TableStyle {
+    match (parent, style) {
+        (Item::Value(_), _) => TableStyle::Inline,
+        (Item::Table(table), TableStyle::Header) if table.is_dotted() => TableStyle::Dotted,
+        (Item::Table(table), TableStyle::Auto) if table.is_dotted() => TableStyle::Inline,
+        _ => style,
+    }
+}
+
+/// Style for tables created between an existing parent and a new value.
+/// [TableStyle::Auto] follows the surrounding document: dotted keys inside of dotted tables, tables
+/// already using dotted keys, and documents without any sections. Headers otherwise
+fn intermediate_style(doc: &DocumentMut, parent: &Item, style: TableStyle) -> TableStyle {
+    let style = fit_style(parent, style);
+    if style != TableStyle::Auto {
+        return style;
+    }
+    let dotted = |item: &Item| matches!(item, Item::Table(table) if table.is_dotted());
+    match parent {
+        Item::Table(table) if table.iter().any(|(_, item)| dotted(item)) => TableStyle::Dotted,
+        _ if !has_sections(doc) => TableStyle::Dotted,
+        _ => TableStyle::Header,
+    }
+}
+
+/// Wrap an item in a new table of the given style, under a single key
+fn wrap(key: &str, item: Item, style: TableStyle) -> Result<Item> {
+    match style {
+        TableStyle::Inline => {
+            let mut table = InlineTable::new();
+            table.insert(key, into_value(item)?);
+            Ok(Item::Value(toml_edit::Value::InlineTable(table)))
+        }
+        TableStyle::Dotted => {
+            let mut table = Table::new();
+            table.insert(key, item);
+            table.set_dotted(true);
+            Ok(Item::Table(table))
+        }
+        TableStyle::Header | TableStyle::Auto => {
+            let mut table = Table::new();
+            table.insert(key, item);
+            table.set_implicit(true);
+            Ok(Item::Table(table))
+        }
+    }
+}
+
+/// Set the value at a path. Missing tables along the path are created, written in the given style.
+/// An existing value at the path is replaced, keeping its decor
+pub(crate) fn set_item(
+    doc: &mut DocumentMut,
+    path: &TomlPath,
+    value: &toml::Value,
+    style: TableStyle,
+) -> Result<()> {
+    let steps = steps(path)?;
+    if steps.is_empty() {
+        bail!("Path '{}' points to the root of the document", path);
+    }
+    // Follow the path for as long as it exists
+    let mut existing = 0;
+    let mut item = doc.as_item();
+    while let Some(next) = steps.get(existing).and_then(|step| child(item, step)) {
+        item = next;
+        existing += 1;
+    }
+    let (parents, missing) = steps.split_at(existing.min(steps.len() - 1));
+    let parent = parents
+        .iter()
+        .try_fold(doc.as_item(), |item, step| child(item, step))
+        .ok_or_else(|| eyre!("Nothing found at '{}'", path))?;
+
+    let created = intermediate_style(doc, parent, style);
+    // Values keep the automatic style when the created tables get headers
+    let value_style = match (created, style) {
+        (TableStyle::Header, TableStyle::Auto) => TableStyle::Auto,
+        _ => created,
+    };
+    let mut item = styled_item(value, value_style, steps.len() == 1);
+    for step in missing[1..].iter().rev() {
+        let Step::Key(key) = step else {
+            bail!("Nothing found at '{}', only tables can be created", path);
+        };
+        item = wrap(key, item, created)?;
+    }
+    let parent = get_item_mut(doc, parents, path)?;
+    insert_child(parent, &missing[0], item, path)
+}
+
+/// Remove the item at a path, returning it
+pub(crate) fn remove_item(doc: &mut DocumentMut, path: &TomlPath) -> Result<Item> {
+    let (parents, last) = split_last(path)?;
+    let parent = get_item_mut(doc, &parents, path)?;
+    let removed = match (parent, last) {
+        (Item::Table(table), Step::Key(key)) => table.remove(key),
+        (Item::Value(toml_edit::Value::InlineTable(table)), Step::Key(key)) => {
+            table.remove(key).map(Item::Value)
+        }
+        (Item::Value(toml_edit::Value::Array(array)), Step::Index(index)) => {
+            resolve_index(index, array.len())
+                .filter(|i| *i < array.len())
+                .map(|i| {
+                    let removed = array.remove(i);
+                    if let (0, Some(first)) = (i, array.get_mut(0)) {
+                        // The new first item takes over the spacing after the opening bracket
+                        let prefix = removed.decor().prefix().cloned().unwrap_or_default();
+                        first.decor_mut().set_prefix(prefix);
+                    }
+                    Item::Value(removed)
+                })
+        }
+        (Item::ArrayOfTables(array), Step::Index(index)) => resolve_index(index, array.len())
+            .and_then(|i| {
+                let table = array.get(i).cloned()?;
+                array.remove(i);
+                Some(Item::Table(table))
+            }),
+        _ => None,
+    };
+    removed.ok_or_else(|| eyre!("Nothing found at '{}'", path))
+}
+
+fn renamed(key: &Key, new_key: &str) -> Key {
+    Key::new(new_key)
+        .with_leaf_decor(key.leaf_decor().clone())
+        .with_dotted_decor(key.dotted_decor().clone())
+}
+
+/// The entries of a table or an inline table, with their keys as written
+trait Entries {
+    type Entry;
+    fn keys(&self) -> Vec<String>;
+    fn remove_entry(&mut self, key: &str) -> Option<(Key, Self::Entry)>;
+    fn insert_formatted(&mut self, key: &Key, entry: Self::Entry);
+}
+
+impl Entries for Table {
+    type Entry = Item;
+
+    fn keys(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key.to_string()).collect()
+    }
+
+    fn remove_entry(&mut self, key: &str) -> Option<(Key, Item)> {
+        Table::remove_entry(self, key)
+    }
+
+    fn insert_formatted(&mut self, key: &Key, entry: Item) {
+        Table::insert_formatted(self, key, entry);
+    }
+}
+
+impl Entries for InlineTable {
+    type Entry = toml_edit::Value;
+
+    fn keys(&self) -> Vec<String> {
+        self.iter().map(|(key, _)| key.to_string()).collect()
+    }
+
+    fn remove_entry(&mut self, key: &str) -> Option<(Key, toml_edit::Value)> {
+        InlineTable::remove_entry(self, key)
+    }
+
+    fn insert_formatted(&mut self, key: &Key, entry: toml_edit::Value) {
+        InlineTable::insert_formatted(self, key, entry);
+    }
+}
+
+/// Rename a key of a table in place. Tables can only insert at the end, so every entry is taken
+/// out and put back in order
+fn rename_entry(table: &mut impl Entries, old_key: &str, new_key: &str) {
+    let entries: Vec<_> = table
+        .keys()
+        .iter()
+        .filter_map(|key| table.remove_entry(key))
+        .collect();
+    for (key, entry) in entries {
+        let key = if key.get() == old_key {
+            renamed(&key, new_key)
+        } else {
+            key
+        };
+        table.insert_formatted(&key, entry);
+    }
+}
+
+/// Rename the key at the end of a path. The entry keeps its position, value and comments.
+/// To give it a key in another table, such as `[tool.poetry]` to `[project]`, see [move_item]
+pub(crate) fn rename_key(doc: &mut DocumentMut, path: &TomlPath, new_key: &str) -> Result<()> {
+    let (parents, last) = split_last(path)?;
+    let Step::Key(old_key) = last else {
+        bail!(
+            "Only keys can be renamed, '{}' ends in an array index",
+            path
+        );
+    };
+    let parent = get_item_mut(doc, &parents, path)?;
+    let Some(table) = parent.as_table_like() else {
+        bail!("Nothing found at '{}'", path);
+    };
+    if !table.contains_key(old_key) {
+        bail!("Nothing found at '{}'", path);
+    }
+    if old_key == new_key {
+        return Ok(());
+    }
+    if table.contains_key(new_key) {
+        bail!("Cannot rename '{}', key '{}' already exists", path, new_key);
+    }
+    match parent {
+        Item::Table(table) => rename_entry(table, old_key, new_key),
+        Item::Value(toml_edit::Value::InlineTable(table)) => rename_entry(table, old_key, new_key),
+        _ => unreachable!("as_table_like only accepts tables"),
+    }
+    Ok(())
+}
+
+/// Rename the item at a path to another path, such as `.tool.poetry` to `.project`, with
+/// [move_item]. The destination must not exist yet, while its parent must
+pub(crate) fn rename_path(doc: &mut DocumentMut, from: &TomlPath, to: &TomlPath) -> Result<()> {
+    if get_item(doc, to).is_ok() {
+        bail!("Cannot rename '{}', '{}' already exists", from, to);
+    }
+    move_item(doc, from, to)
+}
+
+/// Move the item at a path to another path, whose parent must already exist. An existing value at
+/// the destination is replaced, as by [insert_item]. Tables keep their comments and their place
+/// among the sections of the document, under their new header
+pub(crate) fn move_item(doc: &mut DocumentMut, from: &TomlPath, to: &TomlPath) -> Result<()> {
+    if is_descendant(to, from)? {
+        bail!("Cannot move '{}' into itself", from);
+    }
+    let item = remove_item(doc, from)?;
+    insert_item(doc, to, item)
+}
+
+/// Whether `path` points inside of `ancestor`
+fn is_descendant(path: &TomlPath, ancestor: &TomlPath) -> Result<bool> {
+    let path = steps(path)?;
+    let ancestor = steps(ancestor)?;
+    Ok(path.len() > ancestor.len() && path.starts_with(&ancestor))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const DOC: &str = r#"
+[package]
+name = "toml-path"
+keywords = ["toml", "jq"]
+
+[[bin]]
+name = "a"
+
+[[bin]]
+name = "b"
+"#;
+
+    fn get(path: &str) -> Result<String> {
+        let doc = DocumentMut::from_str(DOC).unwrap();
+        let path = TomlPath::from_str(path).unwrap();
+        let item = get_item(&doc, &path)?;
+        Ok(item.to_string().trim().to_string())
+    }
+
+    #[test]
+    fn test_get_item() {
+        assert_eq!("\"toml-path\"", get(".package.name").unwrap());
+        assert_eq!("\"jq\"", get(".package.keywords[1]").unwrap());
+        assert_eq!("\"toml\"", get(".package.keywords[-2]").unwrap());
+        assert_eq!("\"b\"", get(".bin[-1].name").unwrap());
+        assert_eq!("\"a\"", get(r#".["bin"][0]["name"]"#).unwrap());
+    }
+
+    #[test]
+    fn test_get_item_missing() {
+        assert!(get(".package.version").is_err());
+        assert!(get(".package.keywords[2]").is_err());
+        assert!(get(".package.keywords[-3]").is_err());
+    }
+
+    fn edit(f: impl Fn(&mut DocumentMut) -> Result<()>) -> Result<String> {
+        let mut doc = DocumentMut::from_str(DOC).unwrap();
+        f(&mut doc)?;
+        Ok(doc.to_string())
+    }
+
+    fn path(s: &str) -> TomlPath {
+        TomlPath::from_str(s).unwrap()
+    }
+
+    fn value(s: &str) -> Item {
+        Item::Value(s.parse().unwrap())
+    }
+
+    #[test]
+    fn test_insert_key() {
+        let result = edit(|doc| insert_item(doc, &path(".package.version"), value("'1.0.0'")));
+        let expected = DOC.replace("jq\"]\n", "jq\"]\nversion = '1.0.0'\n");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_insert_index() {
+        let result = edit(|doc| insert_item(doc, &path(".package.keywords[1]"), value("\"cli\"")));
+        let expected = DOC.replace("\"toml\", \"jq\"", "\"toml\", \"cli\", \"jq\"");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_insert_append() {
+        let result = edit(|doc| insert_item(doc, &path(".package.keywords[-1]"), value("\"cli\"")));
+        let expected = DOC.replace("\"toml\", \"jq\"", "\"toml\", \"jq\", \"cli\"");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_insert_missing_parent() {
+        let result = edit(|doc| insert_item(doc, &path(".lib.name"), value("\"a\"")));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_insert_out_of_bounds() {
+        let result = edit(|doc| insert_item(doc, &path(".package.keywords[3]"), value("1")));
+        assert!(result.is_err());
+    }
+
+    #[test]
+    fn test_replace_keeps_comments() {
+        let doc = "# The name\nname = 'a' # comment\n";
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        replace_item(&mut doc, &path(".name"), value("\"b\"")).unwrap();
+        assert_eq!("# The name\nname = \"b\" # comment\n", doc.to_string());
+    }
+
+    #[test]
+    fn test_remove() {
+        let result = edit(|doc| {
+            remove_item(doc, &path(".package.keywords[0]"))?;
+            remove_item(doc, &path(".bin[0]"))?;
+            Ok(())
+        });
+        let expected = DOC
+            .replace("\"toml\", ", "")
+            .replace("[[bin]]\nname = \"a\"\n\n", "");
+        assert_eq!(expected, result.unwrap());
+    }
+
+    #[test]
+    fn test_remove_missing() {
+        let result = edit(|doc| remove_item(doc, &path(".package.version")).map(|_| ()));
+        assert!(result.is_err());
+    }
+
+    fn styled(path_str: &str, value: &str, style: TableStyle) -> Item {
+        let value: toml::Value = toml::from_str::<toml::Table>(&format!("v = {}", value))
+            .unwrap()
+            .remove("v")
+            .unwrap();
+        value_to_item(&path(path_str), &value, style).unwrap()
+    }
+
+    #[test]
+    fn test_append_array_of_tables() {
+        let doc =
+            "[[bin]]\nname = \"a\"\n\n[[bin]]\nname = \"b\"\n\n[dependencies]\nserde = \"1\"\n";
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        let item = styled(".bin[-1]", "{name = \"c\"}", TableStyle::Auto);
+        insert_item(&mut doc, &path(".bin[-1]"), item).unwrap();
+        let item = styled(".bin[0]", "{name = \"z\"}", TableStyle::Inline);
+        insert_item(&mut doc, &path(".bin[0]"), item).unwrap();
+        let expected = r#"[[bin]]
+name = "z"
+
+[[bin]]
+name = "a"
+
+[[bin]]
+name = "b"
+
+[[bin]]
+name = "c"
+
+[dependencies]
+serde = "1"
+"#;
+        assert_eq!(expected, doc.to_string());
+    }
+
+    #[test]
+    fn test_insert_table_styles() {
+        let value = "[{name = \"a\", test = {harness = false}}]";
+        let insert = |style| {
+            edit(|doc| insert_item(doc, &path(".example"), styled(".example", value, style)))
+                .unwrap()
+                .replace(DOC, "")
+        };
+        let expected = "\n[[example]]\nname = \"a\"\ntest = { harness = false }\n";
+        assert_eq!(expected, insert(TableStyle::Auto));
+        let expected = "\n[[example]]\nname = \"a\"\n\n[example.test]\nharness = false\n";
+        assert_eq!(expected, insert(TableStyle::Header));
+        let expected = "example = [{ name = \"a\", test = { harness = false } }]\n";
+        let result = edit(|doc| {
+            insert_item(
+                doc,
+                &path(".example"),
+                styled(".example", value, TableStyle::Inline),
+            )
+        });
+        assert!(result.unwrap().contains(expected));
+    }
+
+    #[test]
+    fn test_rename_keeps_position_and_comments() {
+        let doc = r#"[package]
+name = "a"
+
+# Test-only dependencies
+[dev_dependencies] # old name
+pretty_assertions = "1"
+
+[build-dependencies]
+cc = { version = "1", features = ["parallel"] }
+"#;
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        rename_key(&mut doc, &path(".dev_dependencies"), "dev-dependencies").unwrap();
+        rename_key(&mut doc, &path(".build-dependencies.cc.version"), "ver").unwrap();
+        rename_key(&mut doc, &path(".package.name"), "the name").unwrap();
+        let expected = r#"[package]
+"the name" = "a"
+
+# Test-only dependencies
+[dev-dependencies] # old name
+pretty_assertions = "1"
+
+[build-dependencies]
+cc = { ver = "1", features = ["parallel"] }
+"#;
+        assert_eq!(expected, doc.to_string());
+    }
+
+    #[test]
+    fn test_rename_path() {
+        let doc = r#"[tool.poetry]
+name = "app" # from pyproject
+
+[tool.poetry.dependencies]
+python = "^3.12"
+
+[tool.black]
+line-length = 100
+"#;
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        rename_path(&mut doc, &path(".tool.poetry"), &path(".project")).unwrap();
+        let expected = r#"[project]
+name = "app" # from pyproject
+
+[project.dependencies]
+python = "^3.12"
+
+[tool.black]
+line-length = 100
+"#;
+        assert_eq!(expected, doc.to_string());
+        let error = rename_path(&mut doc, &path(".tool.black"), &path(".project"));
+        let expected = "Cannot rename '.tool.black', '.project' already exists";
+        assert_eq!(expected, error.unwrap_err().to_string());
+        let error = rename_path(&mut doc, &path(".project"), &path(".project.old"));
+        assert!(error.is_err());
+    }
+
+    #[test]
+    fn test_rename_errors() {
+        let rename =
+            |path_str: &str, new_key: &str| edit(|doc| rename_key(doc, &path(path_str), new_key));
+        assert!(rename(".package.name", "keywords").is_err());
+        assert!(rename(".package.version", "v").is_err());
+        assert!(rename(".bin[0]", "first").is_err());
+        assert_eq!(DOC, rename(".package.name", "name").unwrap());
+    }
+
+    fn set(doc: &str, path_str: &str, value: &str, style: TableStyle) -> Result<String> {
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        let value = toml_edit::Value::from_str(value).unwrap();
+        set_item(
+            &mut doc,
+            &path(path_str),
+            &edit_value_to_value(&value),
+            style,
+        )?;
+        Ok(doc.to_string())
+    }
+
+    const MANIFEST: &str = "[package]\nname = \"a\" # keep\n\n[dependencies]\nserde = \"1\"\n";
+
+    #[test]
+    fn test_set_creates_tables() {
+        let path = ".package.metadata.docs.rs.all-features";
+        let result = |style| set(MANIFEST, path, "true", style).unwrap();
+        let headers = MANIFEST.replace(
+            "\n\n[dependencies]",
+            "\n\n[package.metadata.docs.rs]\nall-features = true\n\n[dependencies]",
+        );
+        assert_eq!(headers, result(TableStyle::Auto));
+        assert_eq!(headers, result(TableStyle::Header));
+        let dotted = MANIFEST.replace("# keep\n", "# keep\nmetadata.docs.rs.all-features = true\n");
+        assert_eq!(dotted, result(TableStyle::Dotted));
+        let inline = MANIFEST.replace(
+            "# keep\n",
+            "# keep\nmetadata = { docs = { rs = { all-features = true } } }\n",
+        );
+        assert_eq!(inline, result(TableStyle::Inline));
+    }
+
+    #[test]
+    fn test_set_table_headers() {
+        let value = "{ a = { b = { c = 1 } }, d = { e = 2, f = { g = 3 } }, h = {} }";
+        let result = set(MANIFEST, ".tool", value, TableStyle::Header).unwrap();
+        // Only tables holding nothing but other tables go without a header
+        let expected = "\n[tool.a.b]\nc = 1\n\n[tool.d]\ne = 2\n\n[tool.d.f]\ng = 3\n\n[tool.h]\n";
+        assert_eq!(format!("{}{}", MANIFEST, expected), result);
+    }
+
+    #[test]
+    fn test_set_follows_document() {
+        let result = set(
+            "name = \"a\"\n",
+            ".package.edition",
+            "2021",
+            TableStyle::Auto,
+        );
+        assert_eq!("name = \"a\"\npackage.edition = 2021\n", result.unwrap());
+        let doc = "[package]\nlints.rust.x = 1\n";
+        let result = set(doc, ".package.metadata.x", "1", TableStyle::Auto);
+        assert_eq!(format!("{}metadata.x = 1\n", doc), result.unwrap());
+        let doc = "a = { b = 1 }\n";
+        let result = set(doc, ".a.c.d", "2", TableStyle::Header);
+        assert_eq!("a = { b = 1, c = { d = 2 } }\n", result.unwrap());
+    }
+
+    #[test]
+    fn test_set_replaces_existing() {
+        let result = set(MANIFEST, ".package.name", "\"b\"", TableStyle::Auto);
+        assert_eq!(MANIFEST.replace("\"a\"", "\"b\""), result.unwrap());
+    }
+
+    #[test]
+    fn test_set_cannot_create_array_items() {
+        assert!(set(MANIFEST, ".package.keywords[0].a", "1", TableStyle::Auto).is_err());
+        assert!(set(DOC, ".bin[5].name", "1", TableStyle::Auto).is_err());
+    }
+
+    #[test]
+    fn test_get_item_multiple_locations() {
+        assert!(get(".package.keywords[0, 1]").is_err());
+        assert!(get(".package.keywords[0:1]").is_err());
+    }
+}
@@ -0,0 +1,166 @@
+use crate::comments::{
+    comments, set_comments, set_leading_comment, set_trailing_comment, Comments,
+};
+use crate::edit::{rename_key, rename_path, set_item, to_value, TableStyle};
+use crate::expr::{Expr, StringPart};
+use crate::merge::{merge, ArrayStrategy};
+use crate::string_formats::{apply_format, text};
+use crate::toml_path::TomlPath;
+use crate::traverse::traverse;
+use eyre::bail;
+use eyre::Result;
+use toml::Value;
+use toml_edit::DocumentMut;
+
+/// Evaluate an expression against a toml into a stream of results. Paths that select several keys
+/// or indexes, such as `.bin[0, 1]`, give a result for each, and the rest of the expression is
+/// evaluated once per result, as in jq. Builtins that read the formatting of the toml, such as
+/// `comments`, also need the format preserving document the toml was read from
+pub fn evaluate(value: &Value, expr: &Expr, doc: Option<&DocumentMut>) -> Result<Vec<Value>> {
+    match expr {
+        Expr::Path(path) => traverse(value, path),
+        Expr::Literal(literal) => Ok(vec![literal.clone()]),
+        Expr::Merge(lhs, rhs) => {
+            let lhs = evaluate(value, lhs, doc)?;
+            let rhs = evaluate(value, rhs, doc)?;
+            let mut results = Vec::new();
+            for rhs in &rhs {
+                for lhs in &lhs {
+                    if !(lhs.is_table() && rhs.is_table()) {
+                        bail!(
+                            "Only tables can be merged, got {} and {}",
+                            lhs.type_str(),
+                            rhs.type_str()
+                        );
+                    }
+                    results.push(merge(lhs, rhs, &ArrayStrategy::Replace));
+                }
+            }
+            Ok(results)
+        }
+        Expr::Pipe(lhs, rhs) => {
+            let mut results = Vec::new();
+            for input in evaluate(value, lhs, doc)? {
+                // The document describes the whole toml, not the input of the right hand side
+                results.extend(evaluate(&input, rhs, None)?);
+            }
+            Ok(results)
+        }
+        Expr::Format(name) => Ok(vec![Value::String(apply_format(name, value)?)]),
+        Expr::Interpolate { format, parts } => {
+            // A string for every combination of the results of its expressions
+            let mut strings = vec![String::new()];
+            for part in parts {
+                match part {
+                    StringPart::Literal(literal) => {
+                        for string in &mut strings {
+                            string.push_str(literal);
+                        }
+                    }
+                    StringPart::Expr(expr) => {
+                        let mut combined = Vec::new();
+                        for value in evaluate(value, expr, doc)? {
+                            let text = match format {
+                                Some(name) => apply_format(name, &value)?,
+                                None => text(&value),
+                            };
+                            combined.extend(strings.iter().map(|string| format!("{string}{text}")));
+                        }
+                        strings = combined;
+                    }
+                }
+            }
+            Ok(strings.into_iter().map(Value::String).collect())
+        }
+        Expr::Call { name, args } if !expr.edits_document() => Ok(vec![call(name, args, doc)?]),
+        Expr::Call { .. } | Expr::Assign { .. } => {
+            // Edits are implemented once, on format preserving documents
+            let mut doc = match doc {
+                Some(doc) => doc.clone(),
+                None => {
+                    let Value::Table(table) = value else {
+                        bail!("Only tables can be edited, got {}", value.type_str());
+                    };
+                    toml_edit::ser::to_document(table)?
+                }
+            };
+            evaluate_edit(&mut doc, expr, TableStyle::Auto)?;
+            Ok(vec![to_value(&doc)])
+        }
+    }
+}
+
+/// Evaluate an expression that has to give exactly one value, such as the argument of a function
+fn evaluate_one(value: &Value, expr: &Expr, doc: Option<&DocumentMut>) -> Result<Value> {
+    let mut results = evaluate(value, expr, doc)?;
+    if results.len() != 1 {
+        bail!(
+            "'{}' must give a single value, got {} results",
+            expr,
+            results.len()
+        );
+    }
+    Ok(results.remove(0))
+}
+
+fn call(name: &str, args: &[Expr], doc: Option<&DocumentMut>) -> Result<Value> {
+    match (name, args) {
+        ("comments", [Expr::Path(path)]) => {
+            let Some(doc) = doc else {
+                bail!("comments needs the toml document, with its formatting and comments");
+            };
+            let found = comments(doc, path)?
+                .into_iter()
+                .map(|(path, comments)| (path.to_string(), comments.to_value()))
+                .collect();
+            Ok(Value::Table(found))
+        }
+        ("comments", [arg]) => bail!("Argument of comments must be a path, got '{}'", arg),
+        _ => bail!("Unknown function '{}/{}'", name, args.len()),
+    }
+}
+
+fn path_arg<'a>(name: &str, arg: &'a Expr) -> Result<&'a TomlPath> {
+    match arg {
+        Expr::Path(path) => Ok(path),
+        _ => bail!("First argument of {} must be a path, got '{}'", name, arg),
+    }
+}
+
+fn string_arg(doc: &DocumentMut, name: &str, arg: &Expr) -> Result<String> {
+    match evaluate_one(&to_value(doc), arg, Some(doc))? {
+        Value::String(string) => Ok(string),
+        _ => bail!("Argument '{}' of {} must be a string", arg, name),
+    }
+}
+
+/// Apply an expression that edits the toml to a format preserving document.
+/// Tables created by the edit are written in the given style
+pub fn evaluate_edit(doc: &mut DocumentMut, expr: &Expr, style: TableStyle) -> Result<()> {
+    if let Expr::Assign { path, value } = expr {
+        let value = evaluate_one(&to_value(doc), value, Some(doc))?;
+        return set_item(doc, path, &value, style);
+    }
+    let Expr::Call { name, args } = expr else {
+        bail!("Expression '{}' does not edit the toml", expr);
+    };
+    match (name.as_str(), args.as_slice()) {
+        ("rename", [path, Expr::Path(to)]) => rename_path(doc, path_arg(name, path)?, to),
+        ("rename", [path, new_key]) => {
+            let new_key = string_arg(doc, name, new_key)?;
+            rename_key(doc, path_arg(name, path)?, &new_key)
+        }
+        ("set_comment", [path, comment]) => {
+            let comment = string_arg(doc, name, comment)?;
+            set_trailing_comment(doc, path_arg(name, path)?, &comment)
+        }
+        ("set_leading_comment", [path, comment]) => {
+            let comment = string_arg(doc, name, comment)?;
+            set_leading_comment(doc, path_arg(name, path)?, &comment)
+        }
+        ("clear_comments", [path]) => {
+            set_comments(doc, path_arg(name, path)?, &Comments::default())
+        }
+        _ => bail!("Unknown function '{}/{}'", name, args.len()),
+    }
+}
@@ -0,0 +1,352 @@
+use crate::string_formats::sh_word;
+use eyre::bail;
+use eyre::Result;
+use std::collections::HashMap;
+use std::hash::{BuildHasher, Hasher, RandomState};
+use toml::Value;
+
+/// Case of the variable names made from keys by [export]
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum KeyCase {
+    /// `PREFIX_PACKAGE_NAME`
+    #[default]
+    Upper,
+    /// `prefix_package_name`
+    Lower,
+    /// Keep the case of the prefix and keys, `PREFIX_package_name`
+    Preserve,
+}
+
+/// Options for [export]
+#[derive(Debug, Clone)]
+pub struct ExportOptions {
+    /// Start of every variable name, joined to the keys with the separator. May be empty
+    pub prefix: String,
+
+    pub keys: KeyCase,
+
+    /// Joins the prefix and the keys of nested tables, `_` by default
+    pub separator: String,
+}
+
+impl Default for ExportOptions {
+    fn default() -> Self {
+        Self {
+            prefix: String::new(),
+            keys: KeyCase::default(),
+            separator: String::from("_"),
+        }
+    }
+}
+
+/// Make a part of a variable name out of a key: anything but ascii letters, digits and `_` becomes
+/// `_`, and the case is changed as asked
+fn mangle(key: &str, keys: KeyCase) -> String {
+    let mangled: String = key
+        .chars()
+        .map(|c| if c.is_ascii_alphanumeric() { c } else { '_' })
+        .collect();
+    match keys {
+        KeyCase::Upper => mangled.to_ascii_uppercase(),
+        KeyCase::Lower => mangled.to_ascii_lowercase(),
+        KeyCase::Preserve => mangled,
+    }
+}
+
+/// Every scalar of a value, along with the parts of its variable name.
+/// Tables are flattened by key and arrays by index
+fn flatten<'a>(
+    value: &'a Value,
+    name: &mut Vec<String>,
+    found: &mut Vec<(Vec<String>, &'a Value)>,
+) {
+    match value {
+        Value::Table(table) => {
+            for (key, value) in table {
+                name.push(key.clone());
+                flatten(value, name, found);
+                name.pop();
+            }
+        }
+        Value::Array(array) => {
+            for (i, value) in array.iter().enumerate() {
+                name.push(i.to_string());
+                flatten(value, name, found);
+                name.pop();
+            }
+        }
+        _ => found.push((name.clone(), value)),
+    }
+}
+
+/// Variable names and their values, such as `("PREFIX_PACKAGE_NAME", "toml-path")`.
+/// Names are unique and valid shell variable names
+fn variables<'a>(value: &'a Value, options: &ExportOptions) -> Result<Vec<(String, &'a Value)>> {
+    let mut found = Vec::new();
+    flatten(value, &mut Vec::new(), &mut found);
+    let mut variables = Vec::new();
+    let mut names: HashMap<String, String> = HashMap::new();
+    for (parts, value) in found {
+        let mut name = mangle(&options.prefix, options.keys);
+        for part in &parts {
+            if !name.is_empty() {
+                name.push_str(&options.separator);
+            }
+            name.push_str(&mangle(part, options.keys));
+        }
+        if name.is_empty() {
+            bail!("A prefix is needed to export {}", value);
+        }
+        if name.starts_with(|c: char| c.is_ascii_digit()) {
+            name.insert(0, '_');
+        }
+        let key = parts.join(".");
+        if let Some(other) = names.insert(name.clone(), key.clone()) {
+            bail!(
+                "Keys '{}' and '{}' would both be exported as {}",
+                other,
+                key,
+                name
+            );
+        }
+        variables.push((name, value));
+    }
+    Ok(variables)
+}
+
+/// Flatten a value into `NAME='value'` lines that are safe to `eval` in a shell, such as
+/// `PREFIX_PACKAGE_NAME='toml-path'`. Tables are flattened by key and arrays by index, with
+/// names made from the prefix and keys as set by the options
+pub fn export(value: &Value, options: &ExportOptions) -> Result<String> {
+    let mut lines = Vec::new();
+    for (name, value) in variables(value, options)? {
+        lines.push(format!("{}={}", name, sh_word(value)?));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// A scalar as a dotenv value. Strings are single quoted, which docker compose and most dotenv
+/// dialects read literally, unless they contain single quotes or line breaks. Those are double
+/// quoted with backslash escapes instead. Dotenv readers expand `$` in double quotes and do not
+/// agree on an escape for it, so such strings with a `$` are an error
+fn dotenv_value(name: &str, value: &Value) -> Result<String> {
+    let string = match value {
+        Value::String(string) => string.clone(),
+        Value::Datetime(date) => date.to_string(),
+        _ => return Ok(value.to_string()),
+    };
+    if !string.contains(['\'', '\n', '\r']) {
+        return Ok(format!("'{}'", string));
+    }
+    if string.contains('$') {
+        bail!(
+            "Cannot write {} as dotenv without dotenv readers expanding its '$', as its quotes or line breaks need double quotes: {:?}",
+            name,
+            string
+        );
+    }
+    let mut quoted = String::from("\"");
+    for c in string.chars() {
+        match c {
+            '\\' => quoted.push_str("\\\\"),
+            '"' => quoted.push_str("\\\""),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            _ => quoted.push(c),
+        }
+    }
+    quoted.push('"');
+    Ok(quoted)
+}
+
+/// Flatten a table into dotenv `KEY=value` lines, such as `PACKAGE__NAME='toml-path'`. Keys of
+/// nested tables and indexes of arrays are joined with `__`
+pub fn dotenv(value: &Value) -> Result<String> {
+    if !value.is_table() {
+        bail!(
+            "Only tables can be written as dotenv, got {}",
+            value.type_str()
+        );
+    }
+    let options = ExportOptions {
+        separator: String::from("__"),
+        ..ExportOptions::default()
+    };
+    let mut lines = Vec::new();
+    for (name, value) in variables(value, &options)? {
+        lines.push(format!("{}={}", name, dotenv_value(&name, value)?));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// A delimiter for a multi-line GitHub Actions output that does not occur in the value
+fn heredoc_delimiter(value: &str) -> String {
+    loop {
+        let mut hasher = RandomState::new().build_hasher();
+        hasher.write(value.as_bytes());
+        let delimiter = format!("ghadelimiter_{:016x}", hasher.finish());
+        if !value.contains(&delimiter) {
+            return delimiter;
+        }
+    }
+}
+
+/// Format a value for the file named by `$GITHUB_OUTPUT` in GitHub Actions. Tables are flattened
+/// into one output per scalar, named after the keys as written, joined with `__`. Other values are
+/// a single output with the given name. Multi-line values are written between heredoc delimiters
+pub fn github_output(value: &Value, name: &str) -> Result<String> {
+    let mut found = Vec::new();
+    let mut prefix = match value {
+        Value::Table(_) => Vec::new(),
+        _ => vec![String::from(name)],
+    };
+    flatten(value, &mut prefix, &mut found);
+    let mut names: HashMap<String, String> = HashMap::new();
+    let mut lines = Vec::new();
+    for (parts, value) in found {
+        let name = parts.join("__");
+        if name.is_empty() || name.contains(['=', '\n', '\r']) {
+            bail!("'{}' cannot be the name of a GitHub Actions output", name);
+        }
+        let key = parts.join(".");
+        if let Some(other) = names.insert(name.clone(), key.clone()) {
+            bail!(
+                "Keys '{}' and '{}' would both be written as output {}",
+                other,
+                key,
+                name
+            );
+        }
+        let value = match value {
+            Value::String(string) => string.clone(),
+            _ => value.to_string(),
+        };
+        if value.contains(['\n', '\r']) {
+            let delimiter = heredoc_delimiter(&value);
+            lines.push(format!("{}<<{}\n{}\n{}", name, delimiter, value, delimiter));
+        } else {
+            lines.push(format!("{}={}", name, value));
+        }
+    }
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TOML: &str = r#"
+[package]
+name = "toml-path"
+description = "it's $HOME\n`ls`"
+keywords = ["cli", "toml"]
+edition = 2021
+
+[package.metadata.docs-rs]
+all-features = true
+"#;
+
+    fn options(prefix: &str, keys: KeyCase) -> ExportOptions {
+        ExportOptions {
+            prefix: String::from(prefix),
+            keys,
+            ..ExportOptions::default()
+        }
+    }
+
+    #[test]
+    fn test_export() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let expected = r#"CARGO_PACKAGE_NAME='toml-path'
+CARGO_PACKAGE_DESCRIPTION='it'\''s $HOME
+`ls`'
+CARGO_PACKAGE_KEYWORDS_0='cli'
+CARGO_PACKAGE_KEYWORDS_1='toml'
+CARGO_PACKAGE_EDITION=2021
+CARGO_PACKAGE_METADATA_DOCS_RS_ALL_FEATURES=true"#;
+        let result = export(&value, &options("cargo", KeyCase::Upper)).unwrap();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_export_key_case() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let value = &value["package"]["metadata"];
+        let result = export(value, &options("", KeyCase::Preserve)).unwrap();
+        assert_eq!("docs_rs_all_features=true", result);
+        let result = export(value, &options("Cargo", KeyCase::Lower)).unwrap();
+        assert_eq!("cargo_docs_rs_all_features=true", result);
+    }
+
+    #[test]
+    fn test_export_scalar() {
+        let value = Value::String(String::from("0.1.0"));
+        let result = export(&value, &options("VERSION", KeyCase::Upper)).unwrap();
+        assert_eq!("VERSION='0.1.0'", result);
+        assert!(export(&value, &options("", KeyCase::Upper)).is_err());
+    }
+
+    #[test]
+    fn test_export_names() {
+        let value: Value = toml::from_str("1st = 1").unwrap();
+        let result = export(&value, &options("", KeyCase::Upper)).unwrap();
+        assert_eq!("_1ST=1", result);
+        let value: Value = toml::from_str("a-b = 1\na_b = 2").unwrap();
+        assert!(export(&value, &options("", KeyCase::Upper)).is_err());
+    }
+
+    #[test]
+    fn test_dotenv() {
+        let mut value: Value = toml::from_str(TOML).unwrap();
+        value["package"]["description"] = Value::from("it's \"quoted\"\n`ls`");
+        let expected = r#"PACKAGE__NAME='toml-path'
+PACKAGE__DESCRIPTION="it's \"quoted\"\n`ls`"
+PACKAGE__KEYWORDS__0='cli'
+PACKAGE__KEYWORDS__1='toml'
+PACKAGE__EDITION=2021
+PACKAGE__METADATA__DOCS_RS__ALL_FEATURES=true"#;
+        assert_eq!(expected, dotenv(&value).unwrap());
+        assert!(dotenv(&value["package"]["name"]).is_err());
+    }
+
+    #[test]
+    fn test_dotenv_dollar() {
+        // Single quotes are read literally, so variables come back unchanged
+        let value: Value = toml::from_str(r#"home = "$HOME ${HOME}""#).unwrap();
+        assert_eq!("HOME='$HOME ${HOME}'", dotenv(&value).unwrap());
+        // Double quotes would be expanded
+        let value: Value = toml::from_str(r#"v = "it's ${HOME}""#).unwrap();
+        let error = dotenv(&value).unwrap_err().to_string();
+        assert!(error.starts_with("Cannot write V as dotenv"), "{}", error);
+        let value: Value = toml::from_str(r#"v = "$HOME\nnext""#).unwrap();
+        assert!(dotenv(&value).is_err());
+    }
+
+    #[test]
+    fn test_github_output() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let result = github_output(&value["package"]["metadata"], "metadata").unwrap();
+        assert_eq!("docs-rs__all-features=true", result);
+        let result = github_output(&value["package"]["keywords"], "keywords").unwrap();
+        assert_eq!("keywords__0=cli\nkeywords__1=toml", result);
+        let result = github_output(&value["package"]["name"], "name").unwrap();
+        assert_eq!("name=toml-path", result);
+        let result = github_output(&value["package"]["description"], "description").unwrap();
+        let (name, rest) = result.split_once("<<").unwrap();
+        let (delimiter, rest) = rest.split_once('\n').unwrap();
+        assert_eq!("description", name);
+        assert!(delimiter.starts_with("ghadelimiter_"));
+        assert_eq!(format!("it's $HOME\n`ls`\n{}", delimiter), rest);
+    }
+
+    #[test]
+    fn test_github_output_names() {
+        let value: Value = toml::from_str("\"a=b\" = 1").unwrap();
+        assert!(github_output(&value, "").is_err());
+        let value: Value = toml::from_str("\"a\\nb\" = 1").unwrap();
+        assert!(github_output(&value, "").is_err());
+        let value: Value = toml::from_str("a__b = 1\na.b = 2").unwrap();
+        assert!(github_output(&value, "").is_err());
+    }
+}
@@ -0,0 +1,425 @@
+use crate::edit::edit_value_to_value;
+use crate::toml_path::{escape, escaped, toml_path, TomlPath};
+use std::fmt;
+use std::str::FromStr;
+use thiserror::Error;
+use toml::Value;
+use winnow::ascii::space0;
+use winnow::combinator::{
+    alt, delimited, opt, preceded, repeat, rest, separated, separated_foldl1,
+};
+use winnow::prelude::*;
+use winnow::token::{none_of, one_of, take_while};
+
+/// A toml-path expression: one or more [TomlPath]s combined with operators.
+/// Impls std::str::FromStr for convenience
+#[derive(Debug, PartialEq, Clone)]
+pub enum Expr {
+    /// A plain toml path, e.g. `.package.name`
+    Path(TomlPath),
+
+    /// A literal value, e.g. `"dev-dependencies"`
+    Literal(Value),
+
+    /// Deep merge of two tables, e.g. `.defaults * .overrides`.
+    /// Keys of the right hand side take precedence, arrays are replaced
+    Merge(Box<Expr>, Box<Expr>),
+
+    /// Call of a builtin function, with arguments separated by semicolons,
+    /// e.g. `rename(.dev_dependencies; "dev-dependencies")` or `rename(.tool.poetry; .project)`
+    Call { name: String, args: Vec<Expr> },
+
+    /// Feed the result of the left hand side to the right hand side, e.g. `.package.keywords | @sh`
+    Pipe(Box<Expr>, Box<Expr>),
+
+    /// Format the input as a string, e.g. `@sh` to quote it for a shell
+    Format(String),
+
+    /// String with expressions interpolated, e.g. `"\(.package.name).tar.gz"`. With a format, such
+    /// as `@uri "https://crates.io/crates/\(.name)"`, interpolated values are formatted with it
+    Interpolate {
+        format: Option<String>,
+        parts: Vec<StringPart>,
+    },
+
+    /// Set the value at a path, creating missing tables along the way,
+    /// e.g. `.package.metadata.docs.rs.all-features = true`.
+    /// The value is a toml value, or an expression evaluated against the toml
+    Assign { path: TomlPath, value: Box<Expr> },
+}
+
+/// Part of an interpolated string
+#[derive(Debug, PartialEq, Clone)]
+pub enum StringPart {
+    /// Text written as is
+    Literal(String),
+
+    /// Expression evaluated against the input, e.g. `\(.name)`
+    Expr(Expr),
+}
+
+impl Expr {
+    /// Whether the expression edits the toml, rather than querying it.
+    /// Edits can be applied to a format preserving document with [crate::eval_edit]
+    pub fn edits_document(&self) -> bool {
+        match self {
+            Expr::Call { name, .. } => matches!(
+                name.as_str(),
+                "rename" | "set_comment" | "set_leading_comment" | "clear_comments"
+            ),
+            Expr::Assign { .. } => true,
+            Expr::Path(_)
+            | Expr::Literal(_)
+            | Expr::Merge(_, _)
+            | Expr::Pipe(_, _)
+            | Expr::Format(_)
+            | Expr::Interpolate { .. } => false,
+        }
+    }
+}
+
+/// Writes the expression in toml-path syntax
+impl fmt::Display for Expr {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Expr::Path(path) => write!(f, "{}", path),
+            Expr::Literal(value) => write!(f, "{}", value),
+            Expr::Merge(lhs, rhs) => write!(f, "{} * {}", lhs, rhs),
+            Expr::Call { name, args } => {
+                let args: Vec<String> = args.iter().map(Expr::to_string).collect();
+                write!(f, "{}({})", name, args.join("; "))
+            }
+            Expr::Pipe(lhs, rhs) => write!(f, "{} | {}", lhs, rhs),
+            Expr::Format(name) => write!(f, "@{}", name),
+            Expr::Interpolate { format, parts } => {
+                if let Some(name) = format {
+                    write!(f, "@{} ", name)?;
+                }
+                write!(f, "\"")?;
+                for part in parts {
+                    match part {
+                        StringPart::Literal(text) => write!(f, "{}", escaped(text))?,
+                        StringPart::Expr(expr) => write!(f, "\\({})", expr)?,
+                    }
+                }
+                write!(f, "\"")
+            }
+            Expr::Assign { path, value } => write!(f, "{} = {}", path, value),
+        }
+    }
+}
+
+fn path(s: &mut &str) -> PResult<Expr> {
+    toml_path.map(Expr::Path).parse_next(s)
+}
+
+fn string_part(s: &mut &str) -> PResult<StringPart> {
+    alt((
+        delimited(("\\(", space0), pipeline, (space0, ")")).map(StringPart::Expr),
+        repeat(1.., alt((none_of(['"', '\\']), escape))).map(StringPart::Literal),
+    ))
+    .parse_next(s)
+}
+
+/// Double quoted string, with backslash escapes and `\(...)` interpolation
+fn string(s: &mut &str) -> PResult<Vec<StringPart>> {
+    delimited('"', repeat(0.., string_part), '"').parse_next(s)
+}
+
+/// A string, which is a literal unless it interpolates expressions
+fn literal(s: &mut &str) -> PResult<Expr> {
+    let parts = string.parse_next(s)?;
+    match parts.as_slice() {
+        [] => Ok(Expr::Literal(Value::String(String::new()))),
+        [StringPart::Literal(text)] => Ok(Expr::Literal(Value::String(text.clone()))),
+        _ => Ok(Expr::Interpolate {
+            format: None,
+            parts,
+        }),
+    }
+}
+
+fn function_name(s: &mut &str) -> PResult<String> {
+    (
+        one_of(|c: char| c.is_ascii_lowercase() || c == '_'),
+        take_while(0.., |c: char| c.is_ascii_alphanumeric() || c == '_'),
+    )
+        .take()
+        .map(|name: &str| name.to_string())
+        .parse_next(s)
+}
+
+fn semicolon(s: &mut &str) -> PResult<()> {
+    delimited(space0, ";", space0).void().parse_next(s)
+}
+
+fn call(s: &mut &str) -> PResult<Expr> {
+    let name = function_name.parse_next(s)?;
+    let args = delimited(
+        ("(", space0),
+        separated(1.., pipeline, semicolon),
+        (space0, ")"),
+    )
+    .parse_next(s)?;
+    Ok(Expr::Call { name, args })
+}
+
+/// `@name`, or `@name "..."` to format the values interpolated into the string
+fn format(s: &mut &str) -> PResult<Expr> {
+    let _ = "@".parse_next(s)?;
+    let name = take_while(1.., |c: char| c.is_ascii_alphanumeric())
+        .map(|name: &str| name.to_string())
+        .parse_next(s)?;
+    match opt(preceded(space0, string)).parse_next(s)? {
+        Some(parts) => Ok(Expr::Interpolate {
+            format: Some(name),
+            parts,
+        }),
+        None => Ok(Expr::Format(name)),
+    }
+}
+
+fn term(s: &mut &str) -> PResult<Expr> {
+    alt((call, format, literal, path)).parse_next(s)
+}
+
+fn star(s: &mut &str) -> PResult<()> {
+    delimited(space0, "*", space0).void().parse_next(s)
+}
+
+fn merge(s: &mut &str) -> PResult<Expr> {
+    separated_foldl1(term, star, |lhs, _, rhs| {
+        Expr::Merge(Box::new(lhs), Box::new(rhs))
+    })
+    .parse_next(s)
+}
+
+fn pipe(s: &mut &str) -> PResult<()> {
+    delimited(space0, "|", space0).void().parse_next(s)
+}
+
+fn pipeline(s: &mut &str) -> PResult<Expr> {
+    separated_foldl1(merge, pipe, |lhs, _, rhs| {
+        Expr::Pipe(Box::new(lhs), Box::new(rhs))
+    })
+    .parse_next(s)
+}
+
+/// A toml value such as `true` or `{ a = 1 }`, or failing that an expression
+fn assigned_value(s: &mut &str) -> PResult<Expr> {
+    rest.verify_map(
+        |value: &str| match value.trim().parse::<toml_edit::Value>() {
+            Ok(value) => Some(Expr::Literal(edit_value_to_value(&value))),
+            Err(_) => delimited(space0, pipeline, space0).parse(value).ok(),
+        },
+    )
+    .parse_next(s)
+}
+
+fn assign(s: &mut &str) -> PResult<Expr> {
+    let path = delimited(space0, toml_path, space0).parse_next(s)?;
+    let _ = "=".parse_next(s)?;
+    let value = assigned_value.parse_next(s)?;
+    Ok(Expr::Assign {
+        path,
+        value: Box::new(value),
+    })
+}
+
+fn expr(s: &mut &str) -> PResult<Expr> {
+    alt((assign, delimited(space0, pipeline, space0))).parse_next(s)
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum ExprError {
+    #[error("Unable to parse expression")]
+    UnableToParse,
+}
+
+impl FromStr for Expr {
+    type Err = ExprError;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        expr.parse(s).map_err(|_| ExprError::UnableToParse)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn path(s: &str) -> Expr {
+        Expr::Path(TomlPath::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn test_expr_path() {
+        let input = ".foo.bar";
+        let expected = Ok(path(".foo.bar"));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_merge() {
+        let input = ".foo * .bar";
+        let expected = Ok(Expr::Merge(Box::new(path(".foo")), Box::new(path(".bar"))));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_merge_no_space() {
+        let input = ".foo*.bar";
+        let expected = Ok(Expr::Merge(Box::new(path(".foo")), Box::new(path(".bar"))));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_merge_left_associative() {
+        let input = ".a * .b * .c";
+        let expected = Ok(Expr::Merge(
+            Box::new(Expr::Merge(Box::new(path(".a")), Box::new(path(".b")))),
+            Box::new(path(".c")),
+        ));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_call() {
+        let input = r#"rename(.dev_dependencies; "dev-dependencies")"#;
+        let expected = Ok(Expr::Call {
+            name: String::from("rename"),
+            args: vec![
+                path(".dev_dependencies"),
+                Expr::Literal(Value::String(String::from("dev-dependencies"))),
+            ],
+        });
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_call_display() {
+        let input = r#"rename( .a.b ;"c" ) * .d"#;
+        let expected = r#"rename(.a.b; "c") * .d"#;
+        let result = Expr::from_str(input).unwrap().to_string();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_bare_name_is_path() {
+        let input = "package.name";
+        let expected = Ok(path("package.name"));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_assign_literal() {
+        let input = ".package.metadata.docs.rs.all-features = true";
+        let expected = Ok(Expr::Assign {
+            path: TomlPath::from_str(".package.metadata.docs.rs.all-features").unwrap(),
+            value: Box::new(Expr::Literal(Value::Boolean(true))),
+        });
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_assign_table() {
+        let input = r#".a={ b = [1, "c"] }"#;
+        let expected = r#".a = { b = [1, "c"] }"#;
+        let result = Expr::from_str(input).unwrap().to_string();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_assign_expression() {
+        let input = ".a = .b * .c";
+        let expected = Ok(Expr::Assign {
+            path: TomlPath::from_str(".a").unwrap(),
+            value: Box::new(Expr::Merge(Box::new(path(".b")), Box::new(path(".c")))),
+        });
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_pipe() {
+        let input = ".a * .b | @sh";
+        let expected = Ok(Expr::Pipe(
+            Box::new(Expr::Merge(Box::new(path(".a")), Box::new(path(".b")))),
+            Box::new(Expr::Format(String::from("sh"))),
+        ));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_pipe_display() {
+        let input = ".a|.b |@sh";
+        let expected = ".a | .b | @sh";
+        let result = Expr::from_str(input).unwrap().to_string();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_interpolate() {
+        let input = r#""\(.name)-\( .version ).tar.gz""#;
+        let expected = Ok(Expr::Interpolate {
+            format: None,
+            parts: vec![
+                StringPart::Expr(path(".name")),
+                StringPart::Literal(String::from("-")),
+                StringPart::Expr(path(".version")),
+                StringPart::Literal(String::from(".tar.gz")),
+            ],
+        });
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_interpolate_format() {
+        let input = r#"@uri "https://crates.io/search?q=\(.name | @text)""#;
+        let expected = Ok(Expr::Interpolate {
+            format: Some(String::from("uri")),
+            parts: vec![
+                StringPart::Literal(String::from("https://crates.io/search?q=")),
+                StringPart::Expr(Expr::Pipe(
+                    Box::new(path(".name")),
+                    Box::new(Expr::Format(String::from("text"))),
+                )),
+            ],
+        });
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_interpolate_display() {
+        let input = r#"@sh"echo \"\(.a|.b)\"\n""#;
+        let expected = r#"@sh "echo \"\(.a | .b)\"\n""#;
+        let result = Expr::from_str(input).unwrap().to_string();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_string_without_interpolation() {
+        let input = r#""a \"b\"""#;
+        let expected = Ok(Expr::Literal(Value::String(String::from(r#"a "b""#))));
+        let result = Expr::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_expr_merge_missing_rhs() {
+        let input = ".foo *";
+        let result = Expr::from_str(input);
+        assert_eq!(Err(ExprError::UnableToParse), result);
+    }
+}
@@ -0,0 +1,204 @@
+use crate::edit::{edit_value_to_value, steps, Step};
+use crate::toml_path::{escaped, toml_path, TomlPath};
+use eyre::Result;
+use eyre::{bail, eyre};
+use std::collections::HashMap;
+use toml::{Table, Value};
+use winnow::ascii::space0;
+use winnow::combinator::{delimited, rest};
+use winnow::prelude::*;
+
+/// A leaf written as a toml value on a single line. Strings always use a basic string, as toml
+/// would write a multi-line string over several lines
+fn leaf(value: &Value) -> String {
+    match value {
+        Value::String(string) => format!("\"{}\"", escaped(string)),
+        Value::Datetime(datetime) => datetime.to_string(),
+        Value::Array(_) => String::from("[]"),
+        Value::Table(_) => String::from("{}"),
+        _ => value.to_string(),
+    }
+}
+
+fn leaves(value: &Value, path: TomlPath, lines: &mut Vec<String>) {
+    match value {
+        Value::Table(table) if !table.is_empty() => {
+            for (key, value) in table {
+                leaves(value, path.join_key(key), lines);
+            }
+        }
+        Value::Array(array) if !array.is_empty() => {
+            for (i, value) in array.iter().enumerate() {
+                leaves(value, path.join_index(i), lines);
+            }
+        }
+        _ => lines.push(format!("{} = {}", path, leaf(value))),
+    }
+}
+
+/// Every leaf of a value as a `.<path> = <value>` line, in the style of gron, such as
+/// `.bin[0].name = "cli"`. Leaves are scalars and empty tables and arrays, and are written as
+/// toml values. See [unflatten] to read the lines back
+pub fn flatten(value: &Value) -> Vec<String> {
+    let mut lines = Vec::new();
+    leaves(value, TomlPath::root(), &mut lines);
+    lines
+}
+
+fn line<'a>(s: &mut &'a str) -> PResult<(TomlPath, &'a str)> {
+    (toml_path, delimited(space0, '=', space0), rest)
+        .map(|(path, _, value)| (path, value))
+        .parse_next(s)
+}
+
+/// Where each index of the lines ended up in its array, by the path to the array as rebuilt so far
+type Positions = HashMap<(String, isize), usize>;
+
+fn insert(
+    root: &mut Value,
+    path: &TomlPath,
+    value: Value,
+    positions: &mut Positions,
+) -> Result<()> {
+    let steps = steps(path)?;
+    let mut current = root;
+    let mut rebuilt = TomlPath::root();
+    for (i, step) in steps.iter().enumerate() {
+        let child = match steps.get(i + 1) {
+            Some(Step::Key(_)) => Value::Table(Table::new()),
+            Some(Step::Index(_)) => Value::Array(Vec::new()),
+            None => value.clone(),
+        };
+        let (child, inserted) = match (current, step) {
+            (Value::Table(table), Step::Key(key)) => {
+                rebuilt = rebuilt.join_key(key);
+                let inserted = !table.contains_key(*key);
+                (table.entry(*key).or_insert(child), inserted)
+            }
+            (Value::Array(array), Step::Index(index)) => {
+                let key = (rebuilt.to_string(), *index);
+                let position = *positions.entry(key).or_insert(array.len());
+                rebuilt = rebuilt.join_index(position);
+                let inserted = position == array.len();
+                if inserted {
+                    array.push(child);
+                }
+                (&mut array[position], inserted)
+            }
+            (_, Step::Key(_)) => bail!("'{}' sets a key of something that is not a table", path),
+            (_, Step::Index(_)) => {
+                bail!("'{}' sets an item of something that is not an array", path)
+            }
+        };
+        if i + 1 == steps.len() && !inserted {
+            bail!("'{}' is set more than once", path);
+        }
+        current = child;
+    }
+    Ok(())
+}
+
+/// Rebuild a toml from `.<path> = <value>` lines, as written by [flatten]. Blank lines are skipped.
+/// Tables and arrays are created as the paths need them. Items of arrays are numbered in the order
+/// their indexes are first seen, so gaps left by filtering out lines are closed up
+pub fn unflatten(lines: &str) -> Result<Table> {
+    let mut root = Value::Table(Table::new());
+    let mut positions = Positions::new();
+    for (i, text) in lines.lines().enumerate() {
+        if text.trim().is_empty() {
+            continue;
+        }
+        let Ok((path, value)) = line.parse(text.trim_end()) else {
+            bail!(
+                "Line {}: expected '.<path> = <value>', got '{}'",
+                i + 1,
+                text
+            );
+        };
+        let value: toml_edit::Value = match value.parse() {
+            Ok(value) => value,
+            Err(e) => bail!("Line {}: invalid toml value '{}': {}", i + 1, value, e),
+        };
+        let value = edit_value_to_value(&value);
+        if steps(&path)?.is_empty() {
+            if value != Value::Table(Table::new()) {
+                bail!("Line {}: the root can only be set to an empty table", i + 1);
+            }
+            continue;
+        }
+        insert(&mut root, &path, value, &mut positions)
+            .map_err(|e| eyre!("Line {}: {}", i + 1, e))?;
+    }
+    match root {
+        Value::Table(table) => Ok(table),
+        _ => unreachable!("the root is always a table"),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TOML: &str = r#"
+"two words" = "line\nbreak"
+empty = []
+ratio = 1.0
+
+[package]
+name = "toml-path"
+metadata = {}
+
+[[bin]]
+name = "cli"
+released = 1979-05-27
+"#;
+
+    #[test]
+    fn test_flatten() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let expected = vec![
+            r#".["two words"] = "line\nbreak""#,
+            ".empty = []",
+            ".ratio = 1.0",
+            r#".package.name = "toml-path""#,
+            ".package.metadata = {}",
+            r#".bin[0].name = "cli""#,
+            ".bin[0].released = 1979-05-27",
+        ];
+        assert_eq!(expected, flatten(&value));
+        assert_eq!(vec![". = {}"], flatten(&Value::Table(Table::new())));
+    }
+
+    #[test]
+    fn test_unflatten() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let lines = flatten(&value).join("\n");
+        assert_eq!(value, Value::Table(unflatten(&lines).unwrap()));
+        let expected: Table = toml::from_str("a = { b = [1, 2] }").unwrap();
+        assert_eq!(expected, unflatten(".a.b[0]=1\n\n.a.b[1]  =  2\n").unwrap());
+        // Such as after grep
+        let lines = ".bin[3].name = \"a\"\n.bin[7].name = \"b\"\n.bin[3].test = true";
+        let expected: Table =
+            toml::from_str("bin = [{ name = \"a\", test = true }, { name = \"b\" }]").unwrap();
+        assert_eq!(expected, unflatten(lines).unwrap());
+    }
+
+    #[test]
+    fn test_unflatten_errors() {
+        let error = |lines: &str| unflatten(lines).unwrap_err().to_string();
+        assert_eq!(
+            "Line 1: expected '.<path> = <value>', got '.a 1'",
+            error(".a 1")
+        );
+        assert!(error(".a = nope").starts_with("Line 1: invalid toml value 'nope'"));
+        assert_eq!(
+            "Line 2: '.a' is set more than once",
+            error(".a = 1\n.a = 2")
+        );
+        assert_eq!(
+            "Line 2: '.a.b' sets a key of something that is not a table",
+            error(".a = 1\n.a.b = 2")
+        );
+    }
+}
@@ -0,0 +1,434 @@
+use crate::edit::{get_item_mut_at, prefix, raw_str, suffix};
+use crate::toml_path::TomlPath;
+use eyre::bail;
+use eyre::Result;
+use toml_edit::{Array, DocumentMut, InlineTable, Item, KeyMut, Table, Value};
+
+const INDENT: &str = "    ";
+
+/// Options for [format_document]
+#[derive(Debug, Default, Clone)]
+pub struct FmtOptions {
+    /// Sort the keys of every table
+    pub sort_keys: bool,
+
+    /// Sort the keys of only these tables, such as `.dependencies`
+    pub sort_tables: Vec<TomlPath>,
+}
+
+/// Rewrite a toml document in a canonical form. Keys are quoted only when needed, strings use
+/// their default quoting, spacing is normalised, and comments stay attached to their keys.
+/// Keys, including the `[header]` sections of subtables, may also be sorted
+pub fn format_document(doc: &mut DocumentMut, options: &FmtOptions) -> Result<()> {
+    if options.sort_keys {
+        sort_table(doc.as_table_mut(), true);
+    }
+    for path in &options.sort_tables {
+        match get_item_mut_at(doc, path)? {
+            Item::Table(table) => sort_table(table, false),
+            Item::Value(Value::InlineTable(table)) => table.sort_values(),
+            item => bail!("Cannot sort the keys of {} at '{}'", item.type_name(), path),
+        }
+    }
+
+    let first_header = if doc.iter().any(|(_, item)| is_key_value(item)) {
+        None
+    } else {
+        first_position(doc.as_table())
+    };
+    fmt_table(doc.as_table_mut(), first_header);
+    // Comments at the end of the document, without trailing blank lines
+    let trailing = comment_lines(&raw_str(doc.trailing()), "", true);
+    match trailing.trim_end() {
+        "" => doc.set_trailing(""),
+        trailing => doc.set_trailing(format!("{}\n", trailing)),
+    }
+    Ok(())
+}
+
+/// Items written as `key = value`, rather than as a `[header]` section
+fn is_key_value(item: &Item) -> bool {
+    match item {
+        Item::Value(_) => true,
+        Item::Table(table) => table.is_dotted(),
+        Item::ArrayOfTables(_) | Item::None => false,
+    }
+}
+
+/// Tables with `[header]` sections directly inside of a table
+fn sections_mut(item: &mut Item) -> Vec<&mut Table> {
+    match item {
+        Item::Table(table) if !table.is_dotted() => vec![table],
+        Item::ArrayOfTables(array) => array.iter_mut().collect(),
+        _ => Vec::new(),
+    }
+}
+
+fn section_positions(table: &Table, positions: &mut Vec<usize>) {
+    for (_, item) in table.iter() {
+        match item {
+            Item::Table(table) if !table.is_dotted() => {
+                positions.extend(table.position());
+                section_positions(table, positions);
+            }
+            Item::ArrayOfTables(array) => {
+                for table in array.iter() {
+                    positions.extend(table.position());
+                    section_positions(table, positions);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Earliest position of the sections inside a table
+fn first_position(table: &Table) -> Option<usize> {
+    let mut positions = Vec::new();
+    section_positions(table, &mut positions);
+    positions.into_iter().min()
+}
+
+/// Sort the keys of a table, and of every table inside it when `recursive`.
+/// Sections are written in order of their position in the document, so the positions already used
+/// by the sections inside the table are handed out again in sorted order. Sections of other tables
+/// stay where they are
+fn sort_table(table: &mut Table, recursive: bool) {
+    let mut positions = Vec::new();
+    section_positions(table, &mut positions);
+    positions.sort_unstable();
+    sort_keys(table, recursive);
+    reposition(table, &mut positions.into_iter());
+}
+
+fn sort_keys(table: &mut Table, recursive: bool) {
+    table.sort_values();
+    if recursive {
+        for (_, item) in table.iter_mut() {
+            match item {
+                Item::Value(Value::InlineTable(table)) => table.sort_values(),
+                item => {
+                    for table in sections_mut(item) {
+                        sort_keys(table, recursive);
+                    }
+                }
+            }
+        }
+    }
+}
+
+fn reposition(table: &mut Table, positions: &mut impl Iterator<Item = usize>) {
+    for (_, item) in table.iter_mut() {
+        for section in sections_mut(item) {
+            if section.position().is_some() {
+                if let Some(position) = positions.next() {
+                    section.set_position(position);
+                }
+            }
+            reposition(section, positions);
+        }
+    }
+}
+
+/// A trailing comment, such as `  #comment `, written as ` #comment`
+fn trailing_comment(raw: &str) -> String {
+    match raw.trim() {
+        comment if comment.starts_with('#') => format!(" {}", comment),
+        _ => String::new(),
+    }
+}
+
+/// The comment lines of the whitespace and comments found before an item, with each comment
+/// indented and runs of blank lines collapsed into one. Blank lines before the first comment are
+/// only kept with `leading_blank`. The final line is where the item itself starts, so is dropped
+fn comment_lines(raw: &str, indent: &str, leading_blank: bool) -> String {
+    let Some((raw, _)) = raw.rsplit_once('\n') else {
+        return String::new();
+    };
+    let mut lines = String::new();
+    let mut blank = false;
+    for line in raw.split('\n').map(str::trim) {
+        if line.is_empty() {
+            blank = true;
+            continue;
+        }
+        if blank && (leading_blank || !lines.is_empty()) {
+            lines.push('\n');
+        }
+        blank = false;
+        lines.push_str(indent);
+        lines.push_str(line);
+        lines.push('\n');
+    }
+    if blank && (leading_blank || !lines.is_empty()) {
+        lines.push('\n');
+    }
+    lines
+}
+
+/// Normalise the quoting of a key and the whitespace around it, keeping the comments before it
+fn fmt_key(key: &mut KeyMut, leading_blank: bool) {
+    let comments = comment_lines(&prefix(key.leaf_decor()), "", leading_blank);
+    key.fmt();
+    key.leaf_decor_mut().set_prefix(comments);
+}
+
+fn fmt_header(table: &mut Table, first: bool) {
+    let comments = comment_lines(&prefix(table.decor()), "", false);
+    let blank = if first { "" } else { "\n" };
+    let comment = trailing_comment(&suffix(table.decor()));
+    let decor = table.decor_mut();
+    decor.set_prefix(format!("{}{}", blank, comments));
+    decor.set_suffix(comment);
+}
+
+fn fmt_table(table: &mut Table, first_header: Option<usize>) {
+    let mut first = true;
+    for (mut key, item) in table.iter_mut() {
+        match item {
+            Item::Value(value) => {
+                fmt_key(&mut key, !first);
+                let comment = trailing_comment(&suffix(value.decor()));
+                fmt_value(value, 0);
+                value.decor_mut().clear();
+                value.decor_mut().set_suffix(comment);
+                first = false;
+            }
+            Item::Table(table) if table.is_dotted() => {
+                fmt_key(&mut key, !first);
+                fmt_table(table, first_header);
+                first = false;
+            }
+            item => {
+                key.fmt();
+                for section in sections_mut(item) {
+                    let is_first = first_header.is_some() && section.position() == first_header;
+                    fmt_header(section, is_first);
+                    fmt_table(section, first_header);
+                }
+            }
+        }
+    }
+}
+
+/// Normalise the representation of a value and the values inside it, leaving its own decor alone
+fn fmt_value(value: &mut Value, depth: usize) {
+    match value {
+        Value::String(string) => string.fmt(),
+        Value::Array(array) => fmt_array(array, depth),
+        Value::InlineTable(table) => fmt_inline_table(table, depth),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) | Value::Datetime(_) => {}
+    }
+}
+
+fn is_multiline(array: &Array) -> bool {
+    let has_newline = |raw: String| raw.contains('\n');
+    array
+        .iter()
+        .any(|value| has_newline(prefix(value.decor())) || has_newline(suffix(value.decor())))
+        || has_newline(raw_str(array.trailing()))
+}
+
+/// Arrays written over multiple lines keep one item per line, along with their comments.
+/// Other arrays are written on one line
+fn fmt_array(array: &mut Array, depth: usize) {
+    if !is_multiline(array) {
+        array.fmt();
+        for value in array.iter_mut() {
+            fmt_value(value, depth + 1);
+        }
+        return;
+    }
+    let indent = INDENT.repeat(depth + 1);
+    for (i, value) in array.iter_mut().enumerate() {
+        fmt_value(value, depth + 1);
+        let decor = value.decor_mut();
+        decor.set_prefix(multiline_prefix(&prefix(decor), &indent, i > 0));
+        decor.set_suffix("");
+    }
+    let trailing = multiline_prefix(&raw_str(array.trailing()), &indent, true);
+    let trailing = trailing
+        .strip_suffix(&indent)
+        .unwrap_or(&trailing)
+        .to_string();
+    array.set_trailing(format!("{}{}", trailing, INDENT.repeat(depth)));
+    array.set_trailing_comma(true);
+}
+
+/// Whitespace and comments before an item of a multi-line array. The text before the first newline
+/// ends the line of the previous item, so may hold its trailing comment
+fn multiline_prefix(raw: &str, indent: &str, leading_blank: bool) -> String {
+    let (end_of_line, lines) = raw.split_once('\n').unwrap_or((raw, ""));
+    let lines = comment_lines(lines, indent, leading_blank);
+    format!("{}\n{}{}", trailing_comment(end_of_line), lines, indent)
+}
+
+fn fmt_inline_table(table: &mut InlineTable, depth: usize) {
+    table.fmt();
+    table.set_preamble("");
+    for (mut key, value) in table.iter_mut() {
+        key.fmt();
+        fmt_value(value, depth + 1);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    fn fmt(doc: &str, options: &FmtOptions) -> String {
+        let mut doc = DocumentMut::from_str(doc).unwrap();
+        format_document(&mut doc, options).unwrap();
+        doc.to_string()
+    }
+
+    #[test]
+    fn test_fmt_spacing_and_quoting() {
+        let input = r#"
+[ package ]
+"name"    =   'app'   # the name
+keywords = [ 'b',"a" ]
+metadata = {docs={ "rs"=true }}
+[dependencies]
+serde = "1"
+"#;
+        let expected = r#"[package]
+name = "app" # the name
+keywords = ["b", "a"]
+metadata = { docs = { rs = true } }
+
+[dependencies]
+serde = "1"
+"#;
+        assert_eq!(expected, fmt(input, &FmtOptions::default()));
+    }
+
+    #[test]
+    fn test_fmt_keeps_comments() {
+        let input = r#"# Top comment
+name = "app"
+
+
+    # DO NOT BUMP
+version = "0.1.0"
+features = [
+  "a",   # first
+    # about b
+  'b'
+]
+
+# the end
+
+"#;
+        let expected = r#"# Top comment
+name = "app"
+
+# DO NOT BUMP
+version = "0.1.0"
+features = [
+    "a", # first
+    # about b
+    "b",
+]
+
+# the end
+"#;
+        assert_eq!(expected, fmt(input, &FmtOptions::default()));
+    }
+
+    #[test]
+    fn test_fmt_is_idempotent() {
+        let input = "a = [\n1,\n[2, 3]]\n[t]\nb = {c=1}\n";
+        let options = FmtOptions::default();
+        let formatted = fmt(input, &options);
+        assert_eq!(formatted, fmt(&formatted, &options));
+    }
+
+    const DEPENDENCIES: &str = r#"[package]
+version = "0.1.0"
+name = "app"
+
+[dependencies]
+toml = "0.8"
+# DO NOT BUMP
+serde = "1"
+
+[dependencies.clap]
+version = "4"
+
+[dependencies.anyhow]
+version = "1"
+
+[dev-dependencies]
+trycmd = "0.15"
+pretty_assertions = "1"
+"#;
+
+    #[test]
+    fn test_fmt_sort_table() {
+        let options = FmtOptions {
+            sort_keys: false,
+            sort_tables: vec![TomlPath::from_str(".dependencies").unwrap()],
+        };
+        let expected = r#"[package]
+version = "0.1.0"
+name = "app"
+
+[dependencies]
+# DO NOT BUMP
+serde = "1"
+toml = "0.8"
+
+[dependencies.anyhow]
+version = "1"
+
+[dependencies.clap]
+version = "4"
+
+[dev-dependencies]
+trycmd = "0.15"
+pretty_assertions = "1"
+"#;
+        assert_eq!(expected, fmt(DEPENDENCIES, &options));
+    }
+
+    #[test]
+    fn test_fmt_sort_keys() {
+        let options = FmtOptions {
+            sort_keys: true,
+            sort_tables: Vec::new(),
+        };
+        let expected = r#"[dependencies]
+# DO NOT BUMP
+serde = "1"
+toml = "0.8"
+
+[dependencies.anyhow]
+version = "1"
+
+[dependencies.clap]
+version = "4"
+
+[dev-dependencies]
+pretty_assertions = "1"
+trycmd = "0.15"
+
+[package]
+name = "app"
+version = "0.1.0"
+"#;
+        assert_eq!(expected, fmt(DEPENDENCIES, &options));
+    }
+
+    #[test]
+    fn test_fmt_sort_missing_table() {
+        let mut doc = DocumentMut::from_str(DEPENDENCIES).unwrap();
+        let options = FmtOptions {
+            sort_keys: false,
+            sort_tables: vec![TomlPath::from_str(".package.name").unwrap()],
+        };
+        assert!(format_document(&mut doc, &options).is_err());
+    }
+}
@@ -1,144 +1,738 @@
-use crate::settings::Settings;
+use crate::edit::is_array_of_tables;
+use crate::export::dotenv;
+use crate::settings::{Delimiter, OutputFormat, Settings};
+use eyre::bail;
+use eyre::Result;
 use log::debug;
+use std::fmt::Debug;
 use toml::{Table, Value};
+use toml_edit::Item;
 
-fn format_string(s: &str, settings: &Settings) -> String {
-    if settings.raw_output {
-        s.to_string()
+/// Widest line the default output fits nested tables and arrays on, unless the settings give one
+const MAX_WIDTH_DEFAULT: usize = 80;
+
+mod json;
+use json::format_json;
+pub(crate) use json::to_json;
+
+mod toml_output;
+use toml_output::format_toml;
+
+mod yaml;
+use yaml::format_yaml;
+
+/// An output format. Every built in format is one, picked by [Settings::format], and formats of
+/// your own, such as HCL or INI, are registered with [crate::SettingsBuilder::formatter] and
+/// picked with [OutputFormat::Custom]
+pub trait Formatter: Debug {
+    /// Format a single result. The settings give options such as [Settings::indent] and
+    /// [Settings::compact_output], for the formatter to follow as far as its format allows.
+    /// Keys are already sorted when [Settings::sort_keys] is set
+    fn format(&self, value: &Value, settings: &Settings) -> Result<String>;
+
+    /// Format a single result read from an item of a format preserving document, which gives how
+    /// its values were written, such as the notation of integers. See [Settings::original_integers].
+    /// Defaults to [Formatter::format], leaving the source out
+    fn format_from(
+        &self,
+        value: &Value,
+        source: Option<&Item>,
+        settings: &Settings,
+    ) -> Result<String> {
+        let _ = source;
+        self.format(value, settings)
+    }
+}
+
+/// The default output, for [OutputFormat::Raw] and [OutputFormat::Quoted]
+#[derive(Debug)]
+pub(crate) struct Raw;
+
+impl Formatter for Raw {
+    fn format(&self, value: &Value, settings: &Settings) -> Result<String> {
+        self.format_from(value, None, settings)
+    }
+
+    fn format_from(
+        &self,
+        value: &Value,
+        source: Option<&Item>,
+        settings: &Settings,
+    ) -> Result<String> {
+        Ok(format_rec(value, source, settings))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Json;
+
+impl Formatter for Json {
+    fn format(&self, value: &Value, settings: &Settings) -> Result<String> {
+        Ok(format_json(value, settings))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Toml;
+
+impl Formatter for Toml {
+    fn format(&self, value: &Value, settings: &Settings) -> Result<String> {
+        self.format_from(value, None, settings)
+    }
+
+    fn format_from(
+        &self,
+        value: &Value,
+        source: Option<&Item>,
+        settings: &Settings,
+    ) -> Result<String> {
+        Ok(format_toml(value, source, settings))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Yaml;
+
+impl Formatter for Yaml {
+    fn format(&self, value: &Value, _settings: &Settings) -> Result<String> {
+        Ok(format_yaml(value))
+    }
+}
+
+#[derive(Debug)]
+pub(crate) struct Dotenv;
+
+impl Formatter for Dotenv {
+    fn format(&self, value: &Value, _settings: &Settings) -> Result<String> {
+        dotenv(value)
+    }
+}
+
+/// Wrap text in the escape codes of a colour when color output is enabled
+fn paint(code: &str, text: &str, settings: &Settings) -> String {
+    if settings.color_output {
+        format!("\x1b[{}m{}\x1b[0m", code, text)
     } else {
+        text.to_string()
+    }
+}
+
+fn format_string(s: &str, settings: &Settings) -> String {
+    if settings.format == OutputFormat::Quoted {
         // Add surrounding quotes
         format!("\"{}\"", s)
+    } else {
+        s.to_string()
     }
 }
 
-fn format_flat_kv(key: &str, value: &Value, settings: &Settings, level: usize) -> String {
-    let formatted_key = format_string(key, &settings);
-    let formatted_value = format_rec(&value, &settings, level + 1);
-    format!("{}{}{}", formatted_key, settings.separator, formatted_value)
+fn format_key(key: &str, settings: &Settings) -> String {
+    paint(
+        &settings.colors.key,
+        &format_string(key, settings),
+        settings,
+    )
 }
 
-fn flat_format_table(table: &Table, settings: &Settings, level: usize) -> String {
-    let num_items = table.len();
-    match num_items {
-        0 => {
-            return String::from("{}");
-        }
-        1 => {
-            let (k, v) = table.iter().next().unwrap();
-            return format!("{{{}}}", format_flat_kv(&k, &v, &settings, level));
-        }
-        _ => {
-            let mut s = String::from("{");
-            let mut items = table.iter();
-            let mut i = 1;
-            while i < num_items {
-                let (k, v) = items.next().unwrap();
-                let repr = format!(
-                    "{},{}",
-                    format_flat_kv(&k, &v, &settings, level),
-                    settings.spacing
-                );
-                s.push_str(&repr);
-                i += 1;
+/// One level of indentation
+fn indentation(settings: &Settings) -> String {
+    if settings.tab {
+        String::from("\t")
+    } else {
+        " ".repeat(settings.indent)
+    }
+}
+
+/// Columns text takes up in a terminal, leaving out colour escape codes and counting tabs as 8
+fn visible_width(text: &str) -> usize {
+    let mut width = 0;
+    let mut chars = text.chars();
+    while let Some(c) = chars.next() {
+        match c {
+            '\x1b' => {
+                // Skip to the end of the escape sequence, such as `\x1b[0;32m`
+                for c in chars.by_ref() {
+                    if c.is_ascii_alphabetic() {
+                        break;
+                    }
+                }
             }
-            let (final_k, final_v) = items.next().unwrap();
-            let repr = format!("{}}}", format_flat_kv(&final_k, &final_v, &settings, level));
-            s.push_str(&repr);
-            return s;
+            '\t' => width += 8,
+            _ => width += 1,
         }
     }
+    width
 }
 
-fn list_format_table(table: &Table, settings: &Settings, level: usize) -> String {
-    let mut s = String::new();
-    for (k, v) in table.iter() {
-        let repr = format!(
-            "{}{}{}\n",
-            format_string(k, &settings),
-            settings.separator,
-            format_rec(v, &settings, level + 1)
-        );
-        s.push_str(&repr);
+/// Write the keys of every table, including nested ones, in sorted order
+fn sort_keys(value: &Value) -> Value {
+    match value {
+        Value::Table(table) => {
+            let mut entries: Vec<(&String, &Value)> = table.iter().collect();
+            entries.sort_by_key(|(key, _)| *key);
+            let sorted = entries
+                .into_iter()
+                .map(|(key, value)| (key.clone(), sort_keys(value)))
+                .collect();
+            Value::Table(sorted)
+        }
+        Value::Array(array) => Value::Array(array.iter().map(sort_keys).collect()),
+        _ => value.clone(),
     }
-    s
 }
 
-fn pretty_format_table(table: &Table, settings: &Settings, level: usize) -> String {
-    let mut s = String::new();
-    for (k, v) in table.iter() {
-        let title = format!("[{}]\n", format_string(&k, &settings));
-        s.push_str(&title);
-        let value = format!("{}\n", format_rec(&v, &settings, level + 1));
-        s.push_str(&value)
+/// The item at a key of a source item, if there is a source and it has that key
+fn source_child<'a>(source: Option<&'a Item>, key: &str) -> Option<&'a Item> {
+    source.and_then(|item| item.get(key))
+}
+
+/// The item at an index of a source item, if there is a source and it has that index
+fn source_item(source: Option<&Item>, index: usize) -> Option<&Item> {
+    source.and_then(|item| item.get(index))
+}
+
+/// How an integer was written in its source item, such as `0xff`, as long as the source holds
+/// that same integer
+fn original_integer(int: i64, source: Option<&Item>) -> Option<String> {
+    match source?.as_value()? {
+        toml_edit::Value::Integer(formatted) if *formatted.value() == int => {
+            Some(formatted.display_repr().to_string())
+        }
+        _ => None,
     }
-    s
 }
 
-fn format_rec(value: &Value, settings: &Settings, level: usize) -> String {
-    debug!("Formatting {}", value);
+fn format_scalar(value: &Value, source: Option<&Item>, settings: &Settings) -> String {
+    let colors = &settings.colors;
     match value {
-        Value::String(string) => {
-            return format_string(&string, &settings);
-        }
+        Value::String(string) => paint(&colors.string, &format_string(string, settings), settings),
         Value::Integer(int) => {
-            return int.to_string();
+            let original = original_integer(*int, source.filter(|_| settings.original_integers));
+            let int = original.unwrap_or_else(|| int.to_string());
+            paint(&colors.integer, &int, settings)
         }
-        Value::Float(float) => {
-            return float.to_string();
+        // Written as toml, so floats keep their point and inf and nan are valid toml
+        Value::Float(_) => paint(&colors.float, &value.to_string(), settings),
+        Value::Boolean(bool) => paint(&colors.boolean, &bool.to_string(), settings),
+        Value::Datetime(date) => paint(&colors.datetime, &date.to_string(), settings),
+        Value::Array(_) | Value::Table(_) => unreachable!("only called on scalars"),
+    }
+}
+
+/// A value on a single line, such as `[1, 2]` or `{a = 1, b = [3]}`
+fn format_flat(value: &Value, source: Option<&Item>, settings: &Settings) -> String {
+    let delimiter = format!(",{}", settings.spacing);
+    match value {
+        Value::Array(array) => {
+            let items: Vec<String> = array
+                .iter()
+                .enumerate()
+                .map(|(i, item)| format_flat(item, source_item(source, i), settings))
+                .collect();
+            format!("[{}]", items.join(&delimiter))
         }
-        Value::Boolean(bool) => {
-            return bool.to_string();
+        Value::Table(table) => {
+            let entries: Vec<String> = table
+                .iter()
+                .map(|(k, v)| {
+                    let value = format_flat(v, source_child(source, k), settings);
+                    format!("{}{}{}", format_key(k, settings), settings.separator, value)
+                })
+                .collect();
+            format!("{{{}}}", entries.join(&delimiter))
         }
-        Value::Datetime(date) => {
-            return date.to_string();
+        _ => format_scalar(value, source, settings),
+    }
+}
+
+/// A value [fit] can write on a single line, or expanded with an entry per line
+trait Fit: Sized {
+    /// The value on a single line, such as `[1, 2]`
+    fn flat(&self, settings: &Settings) -> String;
+    /// The entries of a table or array, each with what is written before it, such as `key = `.
+    /// `None` for values that always stay on a single line, such as scalars
+    fn entries(&self, settings: &Settings) -> Option<Vec<(String, Self)>>;
+    /// The brackets around expanded entries
+    fn brackets(&self) -> (char, char);
+    /// Whether the last expanded entry is followed by a comma too
+    fn trailing_comma(&self) -> bool {
+        false
+    }
+}
+
+/// Write a value on a single line if it fits in `max_width`, or expanded over multiple lines with
+/// each entry fitted the same way. `depth` is the indentation level of the current line and
+/// `column` how much of it is already taken. Shared by the default, json and toml output
+fn fit<T: Fit>(
+    value: &T,
+    settings: &Settings,
+    max_width: usize,
+    depth: usize,
+    column: usize,
+) -> String {
+    let flat = value.flat(settings);
+    if column + visible_width(&flat) <= max_width {
+        return flat;
+    }
+    let entries = match value.entries(settings) {
+        Some(entries) if !entries.is_empty() => entries,
+        _ => return flat,
+    };
+    let indent = indentation(settings).repeat(depth + 1);
+    let lines: Vec<String> = entries
+        .iter()
+        .map(|(before, entry)| {
+            let column = visible_width(&indent) + visible_width(before);
+            let entry = fit(entry, settings, max_width, depth + 1, column);
+            format!("{}{}{}", indent, before, entry)
+        })
+        .collect();
+    let (open, close) = value.brackets();
+    format!(
+        "{}\n{}{}\n{}{}",
+        open,
+        lines.join(",\n"),
+        if value.trailing_comma() { "," } else { "" },
+        indentation(settings).repeat(depth),
+        close
+    )
+}
+
+/// A value of the default output, along with the item it was read from, if any
+struct Sourced<'a> {
+    value: &'a Value,
+    source: Option<&'a Item>,
+}
+
+impl Fit for Sourced<'_> {
+    fn flat(&self, settings: &Settings) -> String {
+        format_flat(self.value, self.source, settings)
+    }
+
+    fn entries(&self, settings: &Settings) -> Option<Vec<(String, Self)>> {
+        let entries = match self.value {
+            Value::Array(array) => array
+                .iter()
+                .enumerate()
+                .map(|(i, value)| {
+                    let source = source_item(self.source, i);
+                    (String::new(), Sourced { value, source })
+                })
+                .collect(),
+            Value::Table(table) => table
+                .iter()
+                .map(|(k, value)| {
+                    let key = format!("{}{}", format_key(k, settings), settings.separator);
+                    let source = source_child(self.source, k);
+                    (key, Sourced { value, source })
+                })
+                .collect(),
+            _ => return None,
+        };
+        Some(entries)
+    }
+
+    fn brackets(&self) -> (char, char) {
+        match self.value {
+            Value::Array(_) => ('[', ']'),
+            _ => ('{', '}'),
         }
-        Value::Array(array) => {
-            let num_items = array.len();
-            match num_items {
-                0 => return String::from("[]"),
-                1 => {
-                    let item = &array[0];
-                    return format!("[{}]", format_rec(item, &settings, level + 1));
+    }
+}
+
+/// Tables and arrays on a single line if they fit in the maximum width, or expanded over multiple
+/// lines otherwise. See [fit]. `source` is the item the value was read from, if any
+fn format_nested(
+    value: &Value,
+    source: Option<&Item>,
+    settings: &Settings,
+    depth: usize,
+    column: usize,
+) -> String {
+    if settings.compact_output {
+        return format_flat(value, source, settings);
+    }
+    let max_width = settings.max_width.unwrap_or(MAX_WIDTH_DEFAULT);
+    fit(
+        &Sourced { value, source },
+        settings,
+        max_width,
+        depth,
+        column,
+    )
+}
+
+/// A section header of the default output, such as `[package.metadata]` or `[[bin]]`
+fn header(path: &[&str], brackets: (&str, &str), settings: &Settings) -> String {
+    let keys: Vec<String> = path
+        .iter()
+        .map(|key| format_string(key, settings))
+        .collect();
+    let title = format!("{}{}{}", brackets.0, keys.join("."), brackets.1);
+    paint(&settings.colors.header, &title, settings)
+}
+
+/// A header followed by the body of its section, if it has one
+fn section(header: String, body: String) -> String {
+    if body.is_empty() {
+        header
+    } else {
+        format!("{}\n{}", header, body)
+    }
+}
+
+/// A table as `key = value` lines, followed by a section for each of its tables and arrays of
+/// tables, as in a toml document. A table holding only other tables gets no header of its own.
+/// `path` is the keys of the table, for the headers of its sections
+fn format_table(
+    table: &Table,
+    source: Option<&Item>,
+    settings: &Settings,
+    path: &[&str],
+) -> String {
+    let mut lines = Vec::new();
+    let mut sections = Vec::new();
+    for (k, v) in table.iter() {
+        let source = source_child(source, k);
+        let path = [path, &[k.as_str()]].concat();
+        match v {
+            Value::Table(table) => {
+                let body = format_table(table, source, settings, &path);
+                let implicit = !table.is_empty()
+                    && table
+                        .values()
+                        .all(|v| v.is_table() || is_array_of_tables(v));
+                if implicit {
+                    sections.push(body);
+                } else {
+                    sections.push(section(header(&path, ("[", "]"), settings), body));
                 }
-                _ => {
-                    let last_index = num_items - 1;
-                    let mut s = String::from("[");
-                    for item in &array[0..last_index] {
-                        let repr = format!(
-                            "{},{}",
-                            format_rec(&item, &settings, level + 1),
-                            settings.spacing
-                        );
-                        s.push_str(&repr);
-                    }
-                    let final_item = &array[last_index];
-                    let repr = format!("{}]", format_rec(&final_item, &settings, level + 1));
-                    s.push_str(&repr);
-                    return s;
+            }
+            Value::Array(array) if is_array_of_tables(v) => {
+                for (i, item) in array.iter().filter_map(Value::as_table).enumerate() {
+                    let body = format_table(item, source_item(source, i), settings, &path);
+                    sections.push(section(header(&path, ("[[", "]]"), settings), body));
                 }
             }
+            _ => {
+                let key = format!("{}{}", format_key(k, settings), settings.separator);
+                let value = format_nested(v, source, settings, 0, visible_width(&key));
+                lines.push(format!("{}{}", key, value));
+            }
         }
-        Value::Table(table) => {
-            if settings.compact_output || settings.json_output {
-                return flat_format_table(&table, &settings, level);
-            } else {
-                match level {
-                    0 => {
-                        //
-                        return pretty_format_table(&table, &settings, level);
-                    }
-                    1 => {
-                        return list_format_table(&table, &settings, level);
-                    }
-                    _ => {
-                        return flat_format_table(&table, &settings, level);
-                    }
+    }
+    let mut blocks = vec![lines.join("\n")];
+    blocks.extend(sections);
+    blocks.retain(|block| !block.is_empty());
+    blocks.join("\n\n")
+}
+
+fn format_rec(value: &Value, source: Option<&Item>, settings: &Settings) -> String {
+    debug!("Formatting {}", value);
+    match value {
+        Value::Table(table) if !settings.compact_output => {
+            format_table(table, source, settings, &[])
+        }
+        Value::Array(_) | Value::Table(_) => format_nested(value, source, settings, 0, 0),
+        _ => format_scalar(value, source, settings),
+    }
+}
+
+/// Format a value the way [crate::get] and [crate::eval] print their results, such as the value of a [crate::Located]
+pub fn format_value(value: &Value, settings: &Settings) -> Result<String> {
+    format_value_from(value, None, settings)
+}
+
+/// Format a value read from an item of a format preserving document, which gives the original
+/// notation of its integers. See [Settings::original_integers]
+pub(crate) fn format_value_from(
+    value: &Value,
+    source: Option<&Item>,
+    settings: &Settings,
+) -> Result<String> {
+    let sorted;
+    let value = if settings.sort_keys {
+        sorted = sort_keys(value);
+        &sorted
+    } else {
+        value
+    };
+    settings.formatter().format_from(value, source, settings)
+}
+
+/// Join formatted results into the output of a query, ending each one with the delimiter of the
+/// settings
+pub fn join_results(results: &[String], settings: &Settings) -> Result<String> {
+    let mut output = String::new();
+    for result in results {
+        match settings.delimiter {
+            Delimiter::Newline => {
+                output.push_str(result);
+                output.push('\n');
+            }
+            Delimiter::Nul => {
+                if result.contains('\0') {
+                    bail!(
+                        "Cannot delimit results by NUL, as a result contains NUL: {:?}",
+                        result
+                    );
                 }
+                output.push_str(result);
+                output.push('\0');
+            }
+            Delimiter::None => output.push_str(result),
+            Delimiter::RecordSeparator => {
+                output.push('\x1e');
+                output.push_str(result);
+                output.push('\n');
             }
         }
     }
+    Ok(output)
 }
 
-pub fn format_value(value: &Value, settings: &Settings) -> String {
-    return format_rec(&value, &settings, 0);
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::SettingsError;
+    use pretty_assertions::assert_eq;
+
+    fn join(delimiter: Delimiter) -> Result<String> {
+        let results = [String::from("a b"), String::from("c\nd")];
+        let settings = Settings::builder().delimiter(delimiter).build().unwrap();
+        join_results(&results, &settings)
+    }
+
+    #[test]
+    fn test_join_results() {
+        assert_eq!("a b\nc\nd\n", join(Delimiter::Newline).unwrap());
+        assert_eq!("a b\0c\nd\0", join(Delimiter::Nul).unwrap());
+        assert_eq!("a bc\nd", join(Delimiter::None).unwrap());
+        let expected = "\x1ea b\n\x1ec\nd\n";
+        assert_eq!(expected, join(Delimiter::RecordSeparator).unwrap());
+    }
+
+    #[test]
+    fn test_color_output() {
+        let toml = r#"
+[package]
+name = "toml-path"
+released = 1979-05-27
+
+[[bin]]
+test = false
+"#;
+        let value: Value = toml::from_str(toml).unwrap();
+        let settings = Settings::builder().color_output(true).build().unwrap();
+        let expected = "\x1b[1;39m[package]\x1b[0m\n\
+            \x1b[1;34mname\x1b[0m = \x1b[0;32mtoml-path\x1b[0m\n\
+            \x1b[1;34mreleased\x1b[0m = \x1b[0;35m1979-05-27\x1b[0m\n\n\
+            \x1b[1;39m[[bin]]\x1b[0m\n\
+            \x1b[1;34mtest\x1b[0m = \x1b[0;33mfalse\x1b[0m";
+        assert_eq!(expected, format_value(&value, &settings).unwrap());
+    }
+
+    #[test]
+    fn test_max_width() {
+        let toml = r#"
+[package]
+keywords = ["cli", "toml"]
+metadata = { docs = { rs = { all-features = true, targets = ["x86_64-unknown-linux-gnu"] } } }
+"#;
+        let value: Value = toml::from_str(toml).unwrap();
+        let settings = Settings::builder().max_width(Some(30)).build().unwrap();
+        let expected = r#"[package]
+keywords = [cli, toml]
+
+[package.metadata.docs.rs]
+all-features = true
+targets = [
+  x86_64-unknown-linux-gnu
+]"#;
+        assert_eq!(expected, format_value(&value, &settings).unwrap());
+        // Tables in arrays are expanded in braces
+        let expected = r#"[
+  {
+    docs = {
+      rs = {
+        all-features = true,
+        targets = [
+          x86_64-unknown-linux-gnu
+        ]
+      }
+    }
+  }
+]"#;
+        let metadata = Value::Array(vec![value["package"]["metadata"].clone()]);
+        assert_eq!(expected, format_value(&metadata, &settings).unwrap());
+        let settings = Settings::builder()
+            .max_width(Some(10))
+            .tab(true)
+            .build()
+            .unwrap();
+        let expected = "[\n\tcli,\n\ttoml\n]";
+        let result = format_value(&value["package"]["keywords"], &settings).unwrap();
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_table_layout() {
+        let toml = r#"
+name = "app"
+empty = {}
+bin = [{ name = "cli" }, { name = "daemon", test = false }]
+tools = { lint = { deny = ["warnings"] } }
+"#;
+        let value: Value = toml::from_str(toml).unwrap();
+        // Scalars are `key = value` lines whatever their depth, and only tables get a section
+        let expected = r#"name = app
+
+[empty]
+
+[[bin]]
+name = cli
+
+[[bin]]
+name = daemon
+test = false
+
+[tools.lint]
+deny = [warnings]"#;
+        assert_eq!(
+            expected,
+            format_value(&value, &Settings::default()).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sort_keys() {
+        let value: Value = toml::from_str("b = 1\na = { d = 2, c = 3 }").unwrap();
+        let settings = Settings::builder()
+            .sort_keys(true)
+            .output_format(OutputFormat::Json)
+            .build()
+            .unwrap();
+        let expected = r#"{
+  "a": {
+    "c": 3,
+    "d": 2
+  },
+  "b": 1
+}"#;
+        assert_eq!(expected, format_value(&value, &settings).unwrap());
+        let settings = Settings::builder()
+            .sort_keys(true)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let expected = "{a={c=3,d=2},b=1}";
+        assert_eq!(expected, format_value(&value, &settings).unwrap());
+    }
+
+    #[test]
+    fn test_floats() {
+        let value: Value = toml::from_str("a = [1.0, 2.5e-8, inf, -inf, nan]").unwrap();
+        let settings = Settings::default();
+        let expected = "[1.0, 0.000000025, inf, -inf, nan]";
+        assert_eq!(expected, format_value(&value["a"], &settings).unwrap());
+    }
+
+    #[test]
+    fn test_original_integers() {
+        let doc: toml_edit::DocumentMut = "a = 0xff\nb = [1_000, 0o755]\nc = { d = 0b11 }"
+            .parse()
+            .unwrap();
+        let value: Value = toml::from_str(&doc.to_string()).unwrap();
+        let settings = Settings::builder()
+            .original_integers(true)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let expected = "{a=0xff,b=[1_000,0o755],c={d=0b11}}";
+        let formatted = format_value_from(&value, Some(doc.as_item()), &settings).unwrap();
+        assert_eq!(expected, formatted);
+        // A source that no longer holds the same integer is left out
+        let formatted = format_value_from(&Value::Integer(16), Some(&doc["a"]), &settings).unwrap();
+        assert_eq!("16", formatted);
+        let settings = Settings::builder().compact_output(true).build().unwrap();
+        let expected = "{a=255,b=[1000,493],c={d=3}}";
+        let formatted = format_value_from(&value, Some(doc.as_item()), &settings).unwrap();
+        assert_eq!(expected, formatted);
+    }
+
+    /// `key = value` lines of the top level of a table, as in INI
+    #[derive(Debug)]
+    struct Ini;
+
+    impl Formatter for Ini {
+        fn format(&self, value: &Value, settings: &Settings) -> Result<String> {
+            let Value::Table(table) = value else {
+                bail!("Only tables can be written as ini");
+            };
+            let lines: Vec<String> = table
+                .iter()
+                .map(|(key, value)| format!("{}{}{}", key, settings.separator, value))
+                .collect();
+            Ok(lines.join("\n"))
+        }
+    }
+
+    #[test]
+    fn test_custom_formatter() {
+        let value: Value = toml::from_str("name = \"app\"\nport = 8080").unwrap();
+        let settings = Settings::builder()
+            .formatter("ini", Ini)
+            .output_format(OutputFormat::Custom(String::from("ini")))
+            .sort_keys(true)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let expected = "name=\"app\"\nport=8080";
+        assert_eq!(expected, format_value(&value, &settings).unwrap());
+        assert!(format_value(&Value::Integer(1), &settings).is_err());
+    }
+
+    #[test]
+    fn test_builtin_formatters() {
+        let value: Value = toml::from_str("name = \"app\"").unwrap();
+        let formats = [
+            (OutputFormat::Raw, "name = app"),
+            (OutputFormat::Quoted, "\"name\" = \"app\""),
+            (OutputFormat::Json, "{\n  \"name\": \"app\"\n}"),
+            (OutputFormat::Toml, "name = \"app\""),
+            (OutputFormat::Yaml, "name: app"),
+            (OutputFormat::Dotenv, "NAME='app'"),
+        ];
+        for (format, expected) in formats {
+            // A registered formatter does not replace a built in one of the same name
+            let settings = Settings::builder()
+                .formatter("json", Ini)
+                .output_format(format)
+                .build()
+                .unwrap();
+            let formatted = settings.formatter().format(&value, &settings).unwrap();
+            assert_eq!(expected, formatted);
+            assert_eq!(formatted, format_value(&value, &settings).unwrap());
+        }
+    }
+
+    #[test]
+    fn test_unknown_format() {
+        let error = Settings::builder()
+            .formatter("ini", Ini)
+            .output_format(OutputFormat::Custom(String::from("hcl")))
+            .build()
+            .unwrap_err();
+        assert_eq!(SettingsError::UnknownFormat(String::from("hcl")), error);
+    }
+
+    #[test]
+    fn test_join_results_nul() {
+        let results = [String::from("a\0b")];
+        let settings = Settings::builder()
+            .delimiter(Delimiter::Nul)
+            .build()
+            .unwrap();
+        assert!(join_results(&results, &settings).is_err());
+    }
 }
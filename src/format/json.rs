@@ -0,0 +1,441 @@
+use super::{fit, indentation, Fit};
+use crate::colors::Colors;
+use crate::settings::{DatetimeFormat, Settings};
+use serde::Serialize;
+use serde_json::ser::{CompactFormatter, Formatter, PrettyFormatter};
+use serde_json::{json, Map, Number};
+use std::io::{self, Write};
+use toml::value::Datetime;
+use toml::Value;
+
+/// The kind of a toml datetime, as named in the toml spec
+fn datetime_type(date: &Datetime) -> &'static str {
+    match (&date.date, &date.time, &date.offset) {
+        (Some(_), Some(_), Some(_)) => "offset-datetime",
+        (Some(_), Some(_), None) => "local-datetime",
+        (Some(_), None, _) => "local-date",
+        (None, _, _) => "local-time",
+    }
+}
+
+fn datetime_to_json(date: &Datetime, datetimes: DatetimeFormat) -> serde_json::Value {
+    match datetimes {
+        DatetimeFormat::String => serde_json::Value::String(date.to_string()),
+        DatetimeFormat::Tagged => json!({"type": datetime_type(date), "value": date.to_string()}),
+    }
+}
+
+fn value_to_json(value: &Value, datetimes: DatetimeFormat) -> serde_json::Value {
+    match value {
+        Value::String(string) => serde_json::Value::String(string.clone()),
+        Value::Integer(int) => serde_json::Value::Number(Number::from(*int)),
+        Value::Float(float) => Number::from_f64(*float)
+            .map(serde_json::Value::Number)
+            .unwrap_or(serde_json::Value::Null),
+        Value::Boolean(bool) => serde_json::Value::Bool(*bool),
+        Value::Datetime(date) => datetime_to_json(date, datetimes),
+        Value::Array(array) => serde_json::Value::Array(
+            array
+                .iter()
+                .map(|item| value_to_json(item, datetimes))
+                .collect(),
+        ),
+        Value::Table(table) => {
+            let map: Map<String, serde_json::Value> = table
+                .iter()
+                .map(|(k, v)| (k.clone(), value_to_json(v, datetimes)))
+                .collect();
+            serde_json::Value::Object(map)
+        }
+    }
+}
+
+/// Convert a toml value to its json equivalent.
+/// Json has no datetime type, so datetimes become strings
+pub(crate) fn to_json(value: &Value) -> serde_json::Value {
+    value_to_json(value, DatetimeFormat::String)
+}
+
+/// Escape everything outside of ascii as `\uXXXX`, using surrogate pairs where needed.
+/// Only valid on serialized json, where such characters can only be inside of strings
+fn escape_non_ascii(json: &str) -> String {
+    let mut escaped = String::with_capacity(json.len());
+    for c in json.chars() {
+        if c.is_ascii() {
+            escaped.push(c);
+        } else {
+            for unit in c.encode_utf16(&mut [0; 2]) {
+                escaped.push_str(&format!("\\u{:04x}", unit));
+            }
+        }
+    }
+    escaped
+}
+
+/// Wraps a json formatter, highlighting keys and scalars with ANSI colours when given any
+struct ColorFormatter<'a, F> {
+    inner: F,
+    colors: Option<&'a Colors>,
+    in_key: bool,
+}
+
+impl<'a, F: Formatter> ColorFormatter<'a, F> {
+    fn new(inner: F, colors: Option<&'a Colors>) -> Self {
+        Self {
+            inner,
+            colors,
+            in_key: false,
+        }
+    }
+
+    fn start<W: ?Sized + Write>(
+        &self,
+        writer: &mut W,
+        color: fn(&Colors) -> &String,
+    ) -> io::Result<()> {
+        match self.colors {
+            Some(colors) => write!(writer, "\x1b[{}m", color(colors)),
+            None => Ok(()),
+        }
+    }
+
+    fn reset<W: ?Sized + Write>(&self, writer: &mut W) -> io::Result<()> {
+        match self.colors {
+            Some(_) => writer.write_all(b"\x1b[0m"),
+            None => Ok(()),
+        }
+    }
+}
+
+impl<F: Formatter> Formatter for ColorFormatter<'_, F> {
+    fn write_bool<W: ?Sized + Write>(&mut self, writer: &mut W, value: bool) -> io::Result<()> {
+        self.start(writer, |colors| &colors.boolean)?;
+        self.inner.write_bool(writer, value)?;
+        self.reset(writer)
+    }
+
+    fn write_i64<W: ?Sized + Write>(&mut self, writer: &mut W, value: i64) -> io::Result<()> {
+        self.start(writer, |colors| &colors.integer)?;
+        self.inner.write_i64(writer, value)?;
+        self.reset(writer)
+    }
+
+    fn write_u64<W: ?Sized + Write>(&mut self, writer: &mut W, value: u64) -> io::Result<()> {
+        self.start(writer, |colors| &colors.integer)?;
+        self.inner.write_u64(writer, value)?;
+        self.reset(writer)
+    }
+
+    fn write_f64<W: ?Sized + Write>(&mut self, writer: &mut W, value: f64) -> io::Result<()> {
+        self.start(writer, |colors| &colors.float)?;
+        self.inner.write_f64(writer, value)?;
+        self.reset(writer)
+    }
+
+    fn begin_string<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        match self.in_key {
+            true => self.start(writer, |colors| &colors.key)?,
+            false => self.start(writer, |colors| &colors.string)?,
+        }
+        self.inner.begin_string(writer)
+    }
+
+    fn end_string<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_string(writer)?;
+        self.reset(writer)
+    }
+
+    fn begin_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_array(writer)
+    }
+
+    fn end_array<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array(writer)
+    }
+
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.inner.begin_array_value(writer, first)
+    }
+
+    fn end_array_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_array_value(writer)
+    }
+
+    fn begin_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object(writer)
+    }
+
+    fn end_object<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object(writer)
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        self.in_key = true;
+        self.inner.begin_object_key(writer, first)
+    }
+
+    fn end_object_key<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.in_key = false;
+        self.inner.end_object_key(writer)
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.begin_object_value(writer)
+    }
+
+    fn end_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        self.inner.end_object_value(writer)
+    }
+}
+
+/// Json on a single line with a space after each `,` and `:`, such as `{"a": [1, 2]}`
+struct SpacedFormatter;
+
+impl Formatter for SpacedFormatter {
+    fn begin_array_value<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        match first {
+            true => Ok(()),
+            false => writer.write_all(b", "),
+        }
+    }
+
+    fn begin_object_key<W: ?Sized + Write>(
+        &mut self,
+        writer: &mut W,
+        first: bool,
+    ) -> io::Result<()> {
+        match first {
+            true => Ok(()),
+            false => writer.write_all(b", "),
+        }
+    }
+
+    fn begin_object_value<W: ?Sized + Write>(&mut self, writer: &mut W) -> io::Result<()> {
+        writer.write_all(b": ")
+    }
+}
+
+impl Fit for &serde_json::Value {
+    fn flat(&self, settings: &Settings) -> String {
+        let colors = settings.color_output.then_some(&settings.colors);
+        write_json(self, ColorFormatter::new(SpacedFormatter, colors))
+    }
+
+    fn entries(&self, settings: &Settings) -> Option<Vec<(String, Self)>> {
+        let entries = match self {
+            serde_json::Value::Array(array) => {
+                array.iter().map(|item| (String::new(), item)).collect()
+            }
+            serde_json::Value::Object(object) => object
+                .iter()
+                .map(|(key, value)| {
+                    let key = serde_json::Value::String(key.clone()).to_string();
+                    let key = match settings.color_output {
+                        true => format!("\x1b[{}m{}\x1b[0m: ", settings.colors.key, key),
+                        false => format!("{}: ", key),
+                    };
+                    (key, value)
+                })
+                .collect(),
+            _ => return None,
+        };
+        Some(entries)
+    }
+
+    fn brackets(&self) -> (char, char) {
+        match self {
+            serde_json::Value::Array(_) => ('[', ']'),
+            _ => ('{', '}'),
+        }
+    }
+}
+
+fn write_json<F: Formatter>(json: &serde_json::Value, formatter: F) -> String {
+    let mut out = Vec::new();
+    let mut serializer = serde_json::Serializer::with_formatter(&mut out, formatter);
+    json.serialize(&mut serializer)
+        .expect("json values always serialize");
+    String::from_utf8(out).expect("serde_json writes utf-8")
+}
+
+/// Serialize a toml value as json, either pretty printed with the indentation of the settings or
+/// on a single line. Pretty printed objects and arrays are expanded over multiple lines, unless
+/// they fit in the maximum width of the settings
+pub(crate) fn format_json(value: &Value, settings: &Settings) -> String {
+    let json = value_to_json(value, settings.datetimes);
+    let colors = settings.color_output.then_some(&settings.colors);
+    let formatted = match settings.max_width {
+        _ if settings.compact_output => {
+            write_json(&json, ColorFormatter::new(CompactFormatter, colors))
+        }
+        Some(max_width) => fit(&&json, settings, max_width, 0, 0),
+        None => {
+            let indent = indentation(settings);
+            let formatter = PrettyFormatter::with_indent(indent.as_bytes());
+            write_json(&json, ColorFormatter::new(formatter, colors))
+        }
+    };
+    if settings.ascii_output {
+        escape_non_ascii(&formatted)
+    } else {
+        formatted
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::OutputFormat;
+    use pretty_assertions::assert_eq;
+
+    fn format(toml: &str, settings: &Settings) -> String {
+        let value: Value = toml::from_str(toml).unwrap();
+        format_json(&value, settings)
+    }
+
+    #[test]
+    fn test_escaping() {
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let toml = r#"s = "say \"hi\"\\\n\t\u0001""#;
+        let expected = r#"{"s":"say \"hi\"\\\n\t\u0001"}"#;
+        assert_eq!(expected, format(toml, &settings));
+    }
+
+    #[test]
+    fn test_indent() {
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .indent(4)
+            .build()
+            .unwrap();
+        let expected = "{\n    \"a\": [\n        1\n    ]\n}";
+        assert_eq!(expected, format("a = [1]", &settings));
+    }
+
+    #[test]
+    fn test_tab() {
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .tab(true)
+            .build()
+            .unwrap();
+        let expected = "{\n\t\"a\": [\n\t\t1\n\t]\n}";
+        assert_eq!(expected, format("a = [1]", &settings));
+    }
+
+    #[test]
+    fn test_max_width() {
+        let toml = r#"
+name = "toml-path"
+keywords = ["cli", "toml"]
+nested = { deeper = { numbers = [1, 2, 3, 4, 5, 6, 7, 8] } }
+"#;
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .max_width(Some(30))
+            .build()
+            .unwrap();
+        let expected = r#"{
+  "name": "toml-path",
+  "keywords": ["cli", "toml"],
+  "nested": {
+    "deeper": {
+      "numbers": [
+        1,
+        2,
+        3,
+        4,
+        5,
+        6,
+        7,
+        8
+      ]
+    }
+  }
+}"#;
+        assert_eq!(expected, format(toml, &settings));
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .max_width(Some(80))
+            .build()
+            .unwrap();
+        let expected = r#"{
+  "name": "toml-path",
+  "keywords": ["cli", "toml"],
+  "nested": {"deeper": {"numbers": [1, 2, 3, 4, 5, 6, 7, 8]}}
+}"#;
+        assert_eq!(expected, format(toml, &settings));
+    }
+
+    #[test]
+    fn test_color_output() {
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .compact_output(true)
+            .color_output(true)
+            .build()
+            .unwrap();
+        let toml = r#"a = { "b" = ["c", 1, 1.5, true] }"#;
+        let expected = "{\x1b[1;34m\"a\"\x1b[0m:{\x1b[1;34m\"b\"\x1b[0m:[\x1b[0;32m\"c\"\x1b[0m,\
+            \x1b[0;36m1\x1b[0m,\x1b[0;36m1.5\x1b[0m,\x1b[0;33mtrue\x1b[0m]}}";
+        assert_eq!(expected, format(toml, &settings));
+    }
+
+    #[test]
+    fn test_ascii_output() {
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .compact_output(true)
+            .ascii_output(true)
+            .build()
+            .unwrap();
+        let expected = r#"{"caf\u00e9":"\ud83e\udd80"}"#;
+        assert_eq!(expected, format(r#""café" = "🦀""#, &settings));
+    }
+
+    #[test]
+    fn test_datetimes() {
+        let toml = r#"
+offset = 1979-05-27T07:32:00Z
+local = 1979-05-27T07:32:00
+date = 1979-05-27
+time = 07:32:00
+"#;
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let expected = r#"{"offset":"1979-05-27T07:32:00Z","local":"1979-05-27T07:32:00","date":"1979-05-27","time":"07:32:00"}"#;
+        assert_eq!(expected, format(toml, &settings));
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Json)
+            .compact_output(true)
+            .datetimes(DatetimeFormat::Tagged)
+            .build()
+            .unwrap();
+        let expected = concat!(
+            r#"{"offset":{"type":"offset-datetime","value":"1979-05-27T07:32:00Z"},"#,
+            r#""local":{"type":"local-datetime","value":"1979-05-27T07:32:00"},"#,
+            r#""date":{"type":"local-date","value":"1979-05-27"},"#,
+            r#""time":{"type":"local-time","value":"07:32:00"}}"#
+        );
+        assert_eq!(expected, format(toml, &settings));
+    }
+}
@@ -0,0 +1,278 @@
+use super::{fit, visible_width, Fit};
+use crate::edit::value_to_edit_value;
+use crate::settings::Settings;
+use toml::Value;
+use toml_edit::{DocumentMut, Item};
+
+/// Write the integers of a value the way they were written in its source item, such as `0xff`,
+/// wherever the source holds the same integer
+fn original_integers(value: &mut toml_edit::Value, source: &Item) {
+    match value {
+        toml_edit::Value::Integer(int) => {
+            if let Some(toml_edit::Value::Integer(original)) = source.as_value() {
+                if original.value() == int.value() {
+                    let decor = int.decor().clone();
+                    *int = original.clone();
+                    *int.decor_mut() = decor;
+                }
+            }
+        }
+        toml_edit::Value::Array(array) => {
+            for (i, item) in array.iter_mut().enumerate() {
+                if let Some(source) = source.get(i) {
+                    original_integers(item, source);
+                }
+            }
+        }
+        toml_edit::Value::InlineTable(table) => {
+            for (key, item) in table.iter_mut() {
+                if let Some(source) = source.get(key.get()) {
+                    original_integers(item, source);
+                }
+            }
+        }
+        _ => {}
+    }
+}
+
+/// Like [original_integers], for the values of a table and its subtables
+fn original_table_integers(table: &mut toml_edit::Table, source: &Item) {
+    for (key, item) in table.iter_mut() {
+        let Some(source) = source.get(key.get()) else {
+            continue;
+        };
+        match item {
+            Item::Value(value) => original_integers(value, source),
+            Item::Table(table) => original_table_integers(table, source),
+            Item::ArrayOfTables(tables) => {
+                for (i, table) in tables.iter_mut().enumerate() {
+                    if let Some(source) = source.get(i) {
+                        original_table_integers(table, source);
+                    }
+                }
+            }
+            Item::None => {}
+        }
+    }
+}
+
+/// Toml arrays are expanded with a trailing comma. Inline tables always stay on a single line,
+/// as toml does not allow them to span lines
+impl Fit for &toml_edit::Value {
+    fn flat(&self, _settings: &Settings) -> String {
+        self.to_string().trim().to_string()
+    }
+
+    fn entries(&self, _settings: &Settings) -> Option<Vec<(String, Self)>> {
+        let array = self.as_array()?;
+        Some(array.iter().map(|item| (String::new(), item)).collect())
+    }
+
+    fn brackets(&self) -> (char, char) {
+        ('[', ']')
+    }
+
+    fn trailing_comma(&self) -> bool {
+        true
+    }
+}
+
+/// An array fitted to `max_width` when it starts at `column`. See [fit]
+fn fit_array(
+    value: &toml_edit::Value,
+    settings: &Settings,
+    max_width: usize,
+    column: usize,
+) -> toml_edit::Value {
+    let fitted = fit(&value, settings, max_width, 0, column);
+    let mut fitted: toml_edit::Value = fitted.parse().expect("fitted arrays are valid toml");
+    *fitted.decor_mut() = value.decor().clone();
+    fitted
+}
+
+/// Expand the arrays of a table and its subtables that do not fit in `max_width`
+fn fit_arrays(table: &mut toml_edit::Table, settings: &Settings, max_width: usize) {
+    for (key, item) in table.iter_mut() {
+        match item {
+            Item::Value(value @ toml_edit::Value::Array(_)) => {
+                let column = visible_width(&format!("{} = ", key.display_repr()));
+                *value = fit_array(value, settings, max_width, column);
+            }
+            Item::Table(table) => fit_arrays(table, settings, max_width),
+            Item::ArrayOfTables(tables) => {
+                for table in tables.iter_mut() {
+                    fit_arrays(table, settings, max_width);
+                }
+            }
+            _ => {}
+        }
+    }
+}
+
+/// Format a value as toml that parses back to the same value. Tables become whole documents, with
+/// `[header]` and `[[array-of-tables]]` sections, or inline tables with compact output. Other
+/// values are written as toml values, such as `"two\nlines"` or `[1, 2]`. Arrays that do not fit
+/// in the maximum width of the settings are expanded over multiple lines. `source` is the item the
+/// value was read from, if any
+pub(crate) fn format_toml(value: &Value, source: Option<&Item>, settings: &Settings) -> String {
+    let max_width = settings.max_width.filter(|_| !settings.compact_output);
+    let source = source.filter(|_| settings.original_integers);
+    match value {
+        Value::Table(table) if !settings.compact_output => {
+            let mut document = toml::to_string(table).expect("toml tables always serialize");
+            if max_width.is_some() || source.is_some() {
+                let mut doc: DocumentMut = document.parse().expect("toml writes valid toml");
+                if let Some(source) = source {
+                    original_table_integers(doc.as_table_mut(), source);
+                }
+                if let Some(max_width) = max_width {
+                    fit_arrays(doc.as_table_mut(), settings, max_width);
+                }
+                document = doc.to_string();
+            }
+            // Leave the final newline to the caller, like for every other output
+            document.trim_end_matches('\n').to_string()
+        }
+        // Serializing with toml would move tables after the other keys of inline tables
+        _ => {
+            let mut value = value_to_edit_value(value);
+            if let Some(source) = source {
+                original_integers(&mut value, source);
+            }
+            if let (toml_edit::Value::Array(_), Some(max_width)) = (&value, max_width) {
+                value = fit_array(&value, settings, max_width, 0);
+            }
+            value.to_string()
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::settings::OutputFormat;
+    use pretty_assertions::assert_eq;
+
+    const TOML: &str = r#"
+"two words" = "line\nbreak"
+deep = { a = { b = { c = [1, { d = 2 }] } } }
+
+[[bin]]
+name = "cli"
+
+[[bin]]
+name = "daemon"
+"#;
+
+    #[test]
+    fn test_format_toml_document() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .build()
+            .unwrap();
+        let formatted = format_toml(&value, None, &settings);
+        let expected = r#""two words" = """
+line
+break"""
+
+[deep.a.b]
+c = [1, { d = 2 }]
+
+[[bin]]
+name = "cli"
+
+[[bin]]
+name = "daemon""#;
+        assert_eq!(expected, formatted);
+        assert_eq!(value, toml::from_str::<Value>(&formatted).unwrap());
+    }
+
+    #[test]
+    fn test_format_toml_max_width() {
+        let toml = r#"
+short = [1, 2]
+matrix = [[1, 2, 3], [4, 5, 6, 7, 8, 9, 10, 11, 12]]
+
+[[bin]]
+names = ["toml-path", "tp"]
+"#;
+        let value: Value = toml::from_str(toml).unwrap();
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .max_width(Some(24))
+            .build()
+            .unwrap();
+        let formatted = format_toml(&value, None, &settings);
+        let expected = r#"short = [1, 2]
+matrix = [
+  [1, 2, 3],
+  [
+    4,
+    5,
+    6,
+    7,
+    8,
+    9,
+    10,
+    11,
+    12,
+  ],
+]
+
+[[bin]]
+names = [
+  "toml-path",
+  "tp",
+]"#;
+        assert_eq!(expected, formatted);
+        assert_eq!(value, toml::from_str::<Value>(&formatted).unwrap());
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .max_width(Some(4))
+            .tab(true)
+            .build()
+            .unwrap();
+        let expected = "[\n\t1,\n\t2,\n]";
+        assert_eq!(expected, format_toml(&value["short"], None, &settings));
+    }
+
+    #[test]
+    fn test_format_toml_original_integers() {
+        let toml = "mode = 0o755\nlist = [0xff, 2]\n\n[[bin]]\nsize = 1_000 # bytes\n";
+        let doc: DocumentMut = toml.parse().unwrap();
+        let value: Value = toml::from_str(toml).unwrap();
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .original_integers(true)
+            .build()
+            .unwrap();
+        let expected = "mode = 0o755\nlist = [0xff, 2]\n\n[[bin]]\nsize = 1_000";
+        let formatted = format_toml(&value, Some(doc.as_item()), &settings);
+        assert_eq!(expected, formatted);
+        let formatted = format_toml(&value["list"], Some(&doc["list"]), &settings);
+        assert_eq!("[0xff, 2]", formatted);
+    }
+
+    #[test]
+    fn test_format_toml_fragment() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .compact_output(true)
+            .build()
+            .unwrap();
+        let expected = r#"{ a = { b = { c = [1, { d = 2 }] } } }"#;
+        assert_eq!(expected, format_toml(&value["deep"], None, &settings));
+        let expected = r#"[{ name = "cli" }, { name = "daemon" }]"#;
+        assert_eq!(expected, format_toml(&value["bin"], None, &settings));
+        let settings = Settings::builder()
+            .output_format(OutputFormat::Toml)
+            .build()
+            .unwrap();
+        assert_eq!(
+            r#""cli""#,
+            format_toml(&value["bin"][0]["name"], None, &settings)
+        );
+    }
+}
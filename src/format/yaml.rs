@@ -0,0 +1,281 @@
+use toml::{Table, Value};
+
+/// Words that yaml 1.1 parsers, still common in the wild, read as booleans or null
+const KEYWORDS: [&str; 12] = [
+    "y", "n", "yes", "no", "on", "off", "true", "false", "null", "~", "inf", "nan",
+];
+
+/// Strings that read back as the same string with both yaml 1.1 and 1.2 parsers when left
+/// unquoted. Kept conservative: anything that could be a number, date, keyword or indicator is
+/// quoted
+fn is_plain_safe(string: &str) -> bool {
+    let Some(first) = string.chars().next() else {
+        return false;
+    };
+    first.is_ascii_alphabetic()
+        && !string.ends_with(' ')
+        && !KEYWORDS.contains(&string.to_lowercase().as_str())
+        && string
+            .chars()
+            .all(|c| c.is_ascii_alphanumeric() || "_-./ ".contains(c))
+}
+
+fn quoted(string: &str) -> String {
+    if string.chars().any(char::is_control) {
+        // Json strings are valid double quoted yaml scalars
+        serde_json::Value::String(string.to_string()).to_string()
+    } else {
+        format!("'{}'", string.replace('\'', "''"))
+    }
+}
+
+fn format_key(key: &str) -> String {
+    if is_plain_safe(key) {
+        key.to_string()
+    } else {
+        quoted(key)
+    }
+}
+
+/// Multi-line strings as literal blocks, which keep their lines as they are.
+/// Returns None for strings a literal block cannot hold
+fn literal_block(string: &str, indent: usize) -> Option<String> {
+    let body = string.trim_end_matches('\n');
+    let newlines = string.len() - body.len();
+    if !body.contains('\n')
+        || body.starts_with([' ', '\n'])
+        || string.chars().any(|c| c.is_control() && c != '\n')
+    {
+        return None;
+    }
+    let chomping = match newlines {
+        0 => "-",
+        1 => "",
+        _ => "+",
+    };
+    let mut block = format!("|{}", chomping);
+    let padding = " ".repeat(indent);
+    for line in string.trim_end_matches('\n').split('\n') {
+        block.push('\n');
+        if !line.is_empty() {
+            block.push_str(&padding);
+            block.push_str(line);
+        }
+    }
+    for _ in 1..newlines {
+        block.push('\n');
+    }
+    Some(block)
+}
+
+fn format_float(float: f64) -> String {
+    if float.is_nan() {
+        return String::from(".nan");
+    }
+    if float.is_infinite() {
+        return String::from(if float > 0.0 { ".inf" } else { "-.inf" });
+    }
+    // Debug always includes a fraction or exponent. Yaml 1.1 also needs a dot and a signed exponent
+    let float = format!("{:?}", float);
+    match float.split_once('e') {
+        Some((mantissa, exponent)) => {
+            let mantissa = match mantissa.contains('.') {
+                true => mantissa.to_string(),
+                false => format!("{}.0", mantissa),
+            };
+            let exponent = match exponent.strip_prefix('-') {
+                Some(exponent) => format!("-{}", exponent),
+                None => format!("+{}", exponent),
+            };
+            format!("{}e{}", mantissa, exponent)
+        }
+        None => float,
+    }
+}
+
+/// A value that fits on the line of its key or `-`, if it is one. Block scalars are indented by
+/// `indent`
+fn format_scalar(value: &Value, indent: usize) -> Option<String> {
+    match value {
+        Value::String(string) => Some(if is_plain_safe(string) {
+            string.clone()
+        } else {
+            literal_block(string, indent).unwrap_or_else(|| quoted(string))
+        }),
+        Value::Integer(int) => Some(int.to_string()),
+        Value::Float(float) => Some(format_float(*float)),
+        Value::Boolean(bool) => Some(bool.to_string()),
+        // Quoted, as yaml 1.1 reads local times such as 07:32:00 as numbers in base 60
+        Value::Datetime(date) => Some(quoted(&date.to_string())),
+        Value::Array(array) if array.is_empty() => Some(String::from("[]")),
+        Value::Table(table) if table.is_empty() => Some(String::from("{}")),
+        Value::Array(_) | Value::Table(_) => None,
+    }
+}
+
+/// Write a value after a `key:` or `-` that is already on the current line
+fn write_node(out: &mut String, value: &Value, indent: usize) {
+    match (format_scalar(value, indent + 2), value) {
+        (Some(scalar), _) => {
+            out.push(' ');
+            out.push_str(&scalar);
+        }
+        (None, Value::Table(table)) => {
+            out.push('\n');
+            write_mapping(out, table, indent + 2, false);
+        }
+        (None, Value::Array(array)) => {
+            out.push('\n');
+            write_sequence(out, array, indent + 2, false);
+        }
+        (None, _) => unreachable!("only collections need a block of their own"),
+    }
+}
+
+/// Write the entries of a table, one per line. The first entry continues the current line when
+/// `inline_first`, such as after a `-`
+fn write_mapping(out: &mut String, table: &Table, indent: usize, inline_first: bool) {
+    for (i, (key, value)) in table.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if i > 0 || !inline_first {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push_str(&format_key(key));
+        out.push(':');
+        write_node(out, value, indent);
+    }
+}
+
+fn write_sequence(out: &mut String, array: &[Value], indent: usize, inline_first: bool) {
+    for (i, value) in array.iter().enumerate() {
+        if i > 0 {
+            out.push('\n');
+        }
+        if i > 0 || !inline_first {
+            out.push_str(&" ".repeat(indent));
+        }
+        out.push('-');
+        match (format_scalar(value, indent + 2), value) {
+            (None, Value::Table(table)) => {
+                out.push(' ');
+                write_mapping(out, table, indent + 2, true);
+            }
+            (None, Value::Array(array)) => {
+                out.push(' ');
+                write_sequence(out, array, indent + 2, true);
+            }
+            _ => write_node(out, value, indent),
+        }
+    }
+}
+
+/// Format a toml value as a yaml document that yaml 1.1 and 1.2 parsers read back the same.
+/// Multi-line strings are written as literal blocks and datetimes as quoted RFC 3339 strings
+pub(crate) fn format_yaml(value: &Value) -> String {
+    let mut out = String::new();
+    match (format_scalar(value, 2), value) {
+        (Some(scalar), _) => out.push_str(&scalar),
+        (None, Value::Table(table)) => write_mapping(&mut out, table, 0, false),
+        (None, Value::Array(array)) => write_sequence(&mut out, array, 0, false),
+        (None, _) => unreachable!("only collections need a block of their own"),
+    }
+    out
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn format(toml: &str) -> String {
+        let value: Value = toml::from_str(toml).unwrap();
+        format_yaml(&value)
+    }
+
+    #[test]
+    fn test_format_yaml() {
+        let toml = r#"
+name = "app"
+"two words" = "it's"
+empty = []
+matrix = [[1, 2], [3]]
+
+[[bin]]
+name = "cli"
+path = "src/cli.rs"
+
+[[bin]]
+name = "daemon"
+"#;
+        let expected = r#"name: app
+two words: 'it''s'
+empty: []
+matrix:
+  - - 1
+    - 2
+  - - 3
+bin:
+  - name: cli
+    path: src/cli.rs
+  - name: daemon"#;
+        assert_eq!(expected, format(toml));
+    }
+
+    #[test]
+    fn test_format_yaml_ambiguous_scalars() {
+        let toml = r#"
+strings = ["yes", "On", "1.0", "", "-x", "a: b", "null"]
+released = 1979-05-27T07:32:00Z
+time = 07:32:00
+floats = [1.0, 1e20, 2.5e-8, nan, -inf]
+"#;
+        let expected = r#"strings:
+  - 'yes'
+  - 'On'
+  - '1.0'
+  - ''
+  - '-x'
+  - 'a: b'
+  - 'null'
+released: '1979-05-27T07:32:00Z'
+time: '07:32:00'
+floats:
+  - 1.0
+  - 1.0e+20
+  - 2.5e-8
+  - .nan
+  - -.inf"#;
+        assert_eq!(expected, format(toml));
+    }
+
+    #[test]
+    fn test_format_yaml_multi_line_strings() {
+        let toml = r#"
+script = """
+echo one
+
+echo two
+"""
+trimmed = "one\ntwo"
+kept = "one\ntwo\n\n"
+indented = "  one\ntwo"
+tab = "a\tb"
+"#;
+        let expected = r#"script: |
+  echo one
+
+  echo two
+trimmed: |-
+  one
+  two
+kept: |+
+  one
+  two
+
+indented: "  one\ntwo"
+tab: "a\tb""#;
+        assert_eq!(expected, format(toml));
+    }
+}
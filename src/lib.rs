@@ -5,6 +5,7 @@ use std::fs;
 use std::path::Path;
 use std::str::FromStr;
 use toml::Value;
+use toml_edit::DocumentMut;
 
 mod toml_path;
 pub use toml_path::{Index, Op, TomlPath};
@@ -12,16 +13,108 @@ pub use toml_path::{Index, Op, TomlPath};
 mod traverse;
 use traverse::traverse;
 
+mod expr;
+pub use expr::{Expr, StringPart};
+
+mod eval;
+use eval::{evaluate, evaluate_edit};
+
+mod string_formats;
+pub use string_formats::csv_table;
+
+mod text_table;
+pub use text_table::text_table;
+
+mod export;
+pub use export::{dotenv, export, github_output, ExportOptions, KeyCase};
+
+mod render;
+pub use render::render;
+
+mod flatten;
+pub use flatten::{flatten, unflatten};
+
+mod merge;
+pub use merge::{merge, ArrayStrategy};
+
+mod edit;
+pub use edit::TableStyle;
+
+mod diff;
+pub use diff::{diff, Change, DiffOptions};
+
+mod patch;
+pub use patch::{apply_patch, Operation, Patch};
+
+mod fmt;
+pub use fmt::{format_document, FmtOptions};
+
+mod location;
+pub use location::{locate, Located};
+
+mod comments;
+pub use comments::{comments, set_comments, Comments};
+
+mod colors;
+pub use colors::{Colors, ColorsError};
+
 mod settings;
-pub use settings::Settings;
+pub use settings::{
+    DatetimeFormat, Delimiter, OutputFormat, Settings, SettingsBuilder, SettingsError,
+};
 
 mod format;
-use format::format_value;
+use format::format_value_from;
+pub use format::{format_value, join_results, Formatter};
 
-/// Get value(s) specified by a tomlpath from a toml
+/// Get value(s) specified by a tomlpath from a toml. A path that selects several keys or indexes,
+/// such as `.bin[0, 1]`, gives a result for each, formatted one after another
 pub fn get(toml: &Value, path: &TomlPath, settings: &Settings) -> Result<String> {
-    let value = traverse(&toml, &path.parts())?;
-    Ok(format_value(&value, &settings))
+    format_results(&traverse(toml, path)?, settings)
+}
+
+/// Results formatted one after another, on their own lines
+fn format_results(values: &[Value], settings: &Settings) -> Result<String> {
+    let results: Result<Vec<String>> = values
+        .iter()
+        .map(|value| format_value(value, settings))
+        .collect();
+    Ok(results?.join("\n"))
+}
+
+/// Evaluate a toml-path expression, such as `.defaults * .overrides`, against a toml.
+/// A plain path is the simplest expression, so this is a superset of [get]
+pub fn eval(toml: &Value, expr: &Expr, settings: &Settings) -> Result<String> {
+    format_results(&evaluate(toml, expr, None)?, settings)
+}
+
+/// Evaluate a toml-path expression against a format preserving document into its results.
+/// Unlike [eval], builtins that read the formatting of the toml, such as `comments(.)`, are available
+pub fn eval_results(doc: &DocumentMut, expr: &Expr) -> Result<Vec<Value>> {
+    evaluate(&edit::to_value(doc), expr, Some(doc))
+}
+
+/// Evaluate a toml-path expression against a format preserving document, formatting each of its
+/// results and ending each one with the delimiter of the settings. See [eval_results].
+/// The results of paths to a single location are formatted from the item they were read from, which
+/// keeps the notation of their integers with [Settings::original_integers]
+pub fn eval_document(doc: &DocumentMut, expr: &Expr, settings: &Settings) -> Result<String> {
+    let source = match expr {
+        Expr::Path(path) => edit::get_item(doc, path).ok(),
+        _ => None,
+    };
+    let results: Result<Vec<String>> = eval_results(doc, expr)?
+        .iter()
+        .map(|value| format_value_from(value, source, settings))
+        .collect();
+    join_results(&results?, settings)
+}
+
+/// Evaluate a toml-path expression that edits a toml, such as `rename(.a; "b")` or `.a.b = 1`, on a
+/// format preserving document. Unlike [eval], the formatting and comments of the document are kept.
+/// Tables created along the way are written in the given style. See [Expr::edits_document]
+pub fn eval_edit(doc: &mut DocumentMut, expr: &Expr, style: TableStyle) -> Result<()> {
+    evaluate_edit(doc, expr, style)
 }
 
 /// Convenience wrapper for the [get] function to get a value directly from a file.
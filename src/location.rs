@@ -0,0 +1,245 @@
+use crate::edit::{edit_value_to_value, item_to_value};
+use crate::toml_path::TomlPath;
+use crate::traverse::{resolve, Kind, Tree};
+use eyre::Result;
+use std::ops::Range;
+use toml::Value;
+use toml_edit::{ImDocument, Item, TableLike};
+
+/// A value found by a toml path, with where it was written in the source toml
+#[derive(Debug, PartialEq, Clone)]
+pub struct Located {
+    /// Path to this single value, such as `.bin[1].name`
+    pub path: TomlPath,
+    pub value: Value,
+    /// Byte range in the source, covering the key for values found by key and the value otherwise
+    pub span: Range<usize>,
+    /// Line of the start of the span, starting at 1
+    pub line: usize,
+    /// Column of the start of the span in characters, starting at 1
+    pub column: usize,
+}
+
+/// Something in a parsed document. Entries of arrays of tables are tables, not items
+#[derive(Clone)]
+enum Node<'a> {
+    Item(&'a Item),
+    Table(&'a toml_edit::Table),
+    Value(&'a toml_edit::Value),
+    /// Items of an array found by a range
+    Slice(Vec<Node<'a>>),
+}
+
+impl<'a> Node<'a> {
+    fn table_like(&self) -> Option<&'a dyn TableLike> {
+        match self {
+            Node::Item(item) => item.as_table_like(),
+            Node::Table(table) => Some(*table),
+            Node::Value(value) => value.as_inline_table().map(|table| table as &dyn TableLike),
+            Node::Slice(_) => None,
+        }
+    }
+
+    fn len(&self) -> Option<usize> {
+        match *self {
+            Node::Item(Item::ArrayOfTables(array)) => Some(array.len()),
+            Node::Item(Item::Value(value)) | Node::Value(value) => {
+                value.as_array().map(|a| a.len())
+            }
+            Node::Slice(ref items) => Some(items.len()),
+            _ => None,
+        }
+    }
+
+    fn get(&self, index: usize) -> Option<Node<'a>> {
+        match *self {
+            Node::Item(Item::ArrayOfTables(array)) => array.get(index).map(Node::Table),
+            Node::Item(Item::Value(value)) | Node::Value(value) => {
+                value.as_array()?.get(index).map(Node::Value)
+            }
+            Node::Slice(ref items) => items.get(index).cloned(),
+            _ => None,
+        }
+    }
+
+    /// Slices span from the start of their first item to the end of their last
+    fn span(&self) -> Option<Range<usize>> {
+        match self {
+            Node::Item(item) => item.span(),
+            Node::Table(table) => table.span(),
+            Node::Value(value) => value.span(),
+            Node::Slice(items) => {
+                let start = items.first()?.span()?.start;
+                let end = items.last()?.span()?.end;
+                Some(start..end)
+            }
+        }
+    }
+
+    fn to_value(&self) -> Value {
+        match self {
+            Node::Item(item) => item_to_value(item).unwrap_or(Value::Table(toml::Table::new())),
+            Node::Table(table) => item_to_value(&Item::Table((*table).clone()))
+                .unwrap_or(Value::Table(toml::Table::new())),
+            Node::Value(value) => edit_value_to_value(value),
+            Node::Slice(items) => Value::Array(items.iter().map(Node::to_value).collect()),
+        }
+    }
+}
+
+/// A node with the span to report for it: the key for values found by key, and the value
+/// otherwise. Nodes written without a span of their own, such as dotted tables, report the span of
+/// their parent
+#[derive(Clone)]
+struct Spanned<'a> {
+    node: Node<'a>,
+    span: Range<usize>,
+}
+
+impl Tree for Spanned<'_> {
+    fn kind(&self) -> Kind {
+        match (self.node.table_like(), self.node.len()) {
+            (Some(_), _) => Kind::Table,
+            (_, Some(len)) => Kind::Array(len),
+            _ => Kind::Other(self.node.to_value().type_str()),
+        }
+    }
+
+    fn key(&self, key: &str) -> Option<Self> {
+        let (found_key, item) = self.node.table_like()?.get_key_value(key)?;
+        let node = Node::Item(item);
+        let span = found_key.span().or_else(|| node.span());
+        Some(Spanned {
+            node,
+            span: span.unwrap_or_else(|| self.span.clone()),
+        })
+    }
+
+    fn index(&self, index: usize) -> Option<Self> {
+        let node = self.node.get(index)?;
+        Some(Spanned {
+            span: node.span().unwrap_or_else(|| self.span.clone()),
+            node,
+        })
+    }
+
+    fn slice(&self, indexes: &[usize]) -> Self {
+        let node = Node::Slice(indexes.iter().filter_map(|i| self.node.get(*i)).collect());
+        Spanned {
+            span: node.span().unwrap_or_else(|| self.span.clone()),
+            node,
+        }
+    }
+}
+
+/// 1 based line and column of a byte offset
+fn line_column(source: &str, offset: usize) -> (usize, usize) {
+    let before = &source[..offset];
+    let line = before.matches('\n').count() + 1;
+    let line_start = before.rfind('\n').map_or(0, |i| i + 1);
+    (line, before[line_start..].chars().count() + 1)
+}
+
+/// Find every value a toml path points to in a toml source, along with where it was written.
+/// Paths that select several keys or indexes, such as `.bin[0, 1]`, give one result for each
+pub fn locate(source: &str, path: &TomlPath) -> Result<Vec<Located>> {
+    let doc = ImDocument::parse(source)?;
+    let root = Spanned {
+        node: Node::Item(doc.as_item()),
+        span: 0..0,
+    };
+    Ok(resolve(root, path)?
+        .into_iter()
+        .map(|(path, found)| {
+            let (line, column) = line_column(source, found.span.start);
+            Located {
+                path,
+                value: found.node.to_value(),
+                span: found.span,
+                line,
+                column,
+            }
+        })
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const SOURCE: &str = r#"[package]
+name = "app"
+metadata.docs = { rs = true }
+
+[[bin]]
+name = "cli"
+
+[[bin]]
+name = "daemon"
+tags = ["a", "ü", "c"]
+"#;
+
+    fn locations(path: &str) -> Vec<(String, usize, usize)> {
+        let path = TomlPath::from_str(path).unwrap();
+        locate(SOURCE, &path)
+            .unwrap()
+            .into_iter()
+            .map(|found| (found.path.to_string(), found.line, found.column))
+            .collect()
+    }
+
+    #[test]
+    fn test_locate_keys() {
+        let expected = vec![(String::from(".package.name"), 2, 1)];
+        assert_eq!(expected, locations(".package.name"));
+        let expected = vec![(String::from(".package.metadata.docs.rs"), 3, 19)];
+        assert_eq!(expected, locations(".package.metadata.docs.rs"));
+        let expected = vec![(String::from(".package"), 1, 2)];
+        assert_eq!(expected, locations(".package"));
+    }
+
+    #[test]
+    fn test_locate_indexes() {
+        let expected = vec![
+            (String::from(".bin[0].name"), 6, 1),
+            (String::from(".bin[1].name"), 9, 1),
+        ];
+        assert_eq!(expected, locations(".bin[0, -1].name"));
+        let expected = vec![
+            (String::from(".bin[1].tags[1]"), 10, 14),
+            (String::from(".bin[1].tags[2]"), 10, 19),
+            (String::from(".bin[1].tags[0]"), 10, 9),
+        ];
+        assert_eq!(expected, locations(".bin[1].tags[1:3, 0]"));
+        let expected = vec![(String::from(".bin[1].tags[1:3]"), 10, 14)];
+        assert_eq!(expected, locations(".bin[1].tags[1:3]"));
+    }
+
+    #[test]
+    fn test_locate_slice() {
+        let path = TomlPath::from_str(".bin[1].tags[-2:3]").unwrap();
+        let found = locate(SOURCE, &path).unwrap();
+        assert_eq!(1, found.len());
+        let expected = Value::Array(vec![Value::from("ü"), Value::from("c")]);
+        assert_eq!(expected, found[0].value);
+        assert_eq!("\"ü\", \"c\"", &SOURCE[found[0].span.clone()]);
+    }
+
+    #[test]
+    fn test_locate_values() {
+        let path = TomlPath::from_str(".package.name").unwrap();
+        let found = locate(SOURCE, &path).unwrap();
+        assert_eq!(Value::String(String::from("app")), found[0].value);
+        assert_eq!(10..14, found[0].span);
+    }
+
+    #[test]
+    fn test_locate_errors() {
+        let path = TomlPath::from_str(".package.missing").unwrap();
+        assert!(locate(SOURCE, &path).is_err());
+        let path = TomlPath::from_str(".bin[5]").unwrap();
+        assert!(locate(SOURCE, &path).is_err());
+    }
+}
@@ -1,21 +1,51 @@
-use clap::Parser;
+use clap::{Parser, Subcommand, ValueEnum};
 use color_eyre::eyre::bail;
 use color_eyre::Result;
 use log::debug;
+use std::env;
+use std::ffi::{OsStr, OsString};
 use std::fs;
 use std::io::BufRead;
+use std::io::IsTerminal;
+use std::io::Write;
 use std::io::{self};
-use std::path::PathBuf;
+use std::path::{Path, PathBuf};
+use std::process;
+use std::str::FromStr;
 use toml::Value;
-use toml_path::get;
+use toml_edit::DocumentMut;
+use toml_path::ArrayStrategy;
+use toml_path::Colors;
+use toml_path::DatetimeFormat;
+use toml_path::Delimiter;
+use toml_path::DiffOptions;
+use toml_path::Expr;
+use toml_path::FmtOptions;
+use toml_path::Op;
+use toml_path::OutputFormat;
+use toml_path::Patch;
 use toml_path::Settings;
+use toml_path::TableStyle;
 use toml_path::TomlPath;
+use toml_path::{
+    apply_patch, csv_table, diff, flatten, format_document, merge, render, text_table, unflatten,
+};
+use toml_path::{
+    eval_document, eval_edit, eval_results, export, format_value, github_output, join_results,
+    locate,
+};
+use toml_path::{ExportOptions, KeyCase};
 
 #[derive(Debug, Parser)]
 #[command(version, about, long_about=None)]
+#[command(args_conflicts_with_subcommands = true, subcommand_negates_reqs = true)]
 struct Cli {
-    /// Toml path
-    path: TomlPath,
+    #[command(subcommand)]
+    command: Option<Command>,
+
+    /// Toml path expression
+    #[arg(required = true)]
+    path: Option<Expr>,
 
     /// Toml file to process. Toml content is read from stdin if omitted
     file: Option<PathBuf>,
@@ -28,9 +58,433 @@ struct Cli {
     #[arg(short, long)]
     compact_output: bool,
 
+    /// Write strings directly like '--raw-output', with a NUL after each result instead of a newline
+    #[arg(long, conflicts_with_all = ["join_output", "json_output", "toml_output", "yaml_output", "format"])]
+    raw_output0: bool,
+
+    /// Write strings directly like '--raw-output', without anything between results
+    #[arg(short, long, conflicts_with_all = ["json_output", "toml_output", "yaml_output", "format"])]
+    join_output: bool,
+
+    /// Write an ASCII record separator before each result, as RFC 7464 json text sequences
+    #[arg(long, conflicts_with_all = ["raw_output0", "join_output"])]
+    seq: bool,
+
     /// Output as json
-    #[arg(short, long, group = "output_format")]
+    #[arg(long, group = "output_format")]
     json_output: bool, // TODO: add more flags like jq
+
+    /// Output as toml that can be parsed back: tables as whole documents, other values as toml values
+    #[arg(short, long, group = "output_format")]
+    toml_output: bool,
+
+    /// Output as yaml, with multi-line strings as literal blocks
+    #[arg(
+        short,
+        long,
+        group = "output_format",
+        conflicts_with = "compact_output"
+    )]
+    yaml_output: bool,
+
+    /// Output in the given format. 'dotenv' flattens tables into 'KEY=value' lines, joining the keys of nested tables with '__'
+    #[arg(
+        long = "output-format",
+        value_enum,
+        value_name = "FORMAT",
+        group = "output_format",
+        conflicts_with = "compact_output"
+    )]
+    format: Option<Format>,
+
+    /// Number of spaces per level of indentation when pretty printing
+    #[arg(long, default_value_t = 2, value_name = "N")]
+    indent: usize,
+
+    /// Indent with a tab per level instead of spaces
+    #[arg(long, conflicts_with = "indent")]
+    tab: bool,
+
+    /// Write the keys of every table in sorted order
+    #[arg(short = 'S', long)]
+    sort_keys: bool,
+
+    /// Expand nested tables and arrays over multiple lines when they do not fit in N columns. Defaults to 80 for the default output, json and toml output are otherwise written as usual
+    #[arg(long, value_name = "N")]
+    max_width: Option<usize>,
+
+    /// Write integers the way they are written in the toml, such as '0xff' or '1_000', in the default and toml output. Applies to paths to a single location
+    #[arg(long)]
+    original_integers: bool,
+
+    /// Escape every non-ascii character in json output as '\uXXXX'
+    #[arg(short, long)]
+    ascii_output: bool,
+
+    /// Colour the default and json output, even when stdout is not a terminal. Colours are read from $TOML_PATH_COLORS
+    #[arg(short = 'C', long, conflicts_with = "monochrome_output")]
+    color_output: bool,
+
+    /// Never colour the output. By default it is coloured when stdout is a terminal and $NO_COLOR is unset
+    #[arg(short = 'M', long)]
+    monochrome_output: bool,
+
+    /// How to write datetimes in json output, which has no datetime type
+    #[arg(long, value_enum, default_value_t = Datetimes::String)]
+    json_datetimes: Datetimes,
+
+    /// Write the result of an editing expression, such as 'rename(...)', back to the file
+    #[arg(short, long, requires = "file")]
+    in_place: bool,
+
+    /// How to write tables created by an editing expression, such as '.a.b.c = 1'
+    #[arg(long, value_enum, default_value_t = Tables::Auto)]
+    table_style: Tables,
+
+    /// Flatten results into 'PREFIX_KEY=value' lines that are safe to eval in a shell
+    #[arg(long, value_name = "PREFIX", conflicts_with_all = ["output_format", "join_output", "raw_output0", "seq", "with_location", "in_place"])]
+    export: Option<String>,
+
+    /// Case of the variable names made from keys by '--export'
+    #[arg(long, value_enum, default_value_t = Keys::Upper, requires = "export")]
+    export_keys: Keys,
+
+    /// Append results to the file named by $GITHUB_OUTPUT, as outputs of a GitHub Actions step. Tables give one output per value, other results are named after the last key of the path
+    #[arg(long, conflicts_with_all = ["output_format", "join_output", "raw_output0", "seq", "export", "with_location", "in_place"])]
+    github_output: bool,
+
+    /// Write arrays of tables as csv, with a header row of every key and a row per table
+    #[arg(long, conflicts_with_all = ["output_format", "join_output", "raw_output0", "seq", "export", "github_output", "with_location", "in_place"])]
+    csv_table: bool,
+
+    /// Draw arrays of tables as an aligned text table, with a header of every key. Cells are truncated to fit '--max-width', or the terminal
+    #[arg(long, conflicts_with_all = ["output_format", "join_output", "raw_output0", "seq", "export", "github_output", "csv_table", "flatten", "with_location", "in_place"])]
+    table: bool,
+
+    /// Print every leaf of the results as a '.<path> = <value>' line, such as '.bin[0].name = "cli"', for grep and diff
+    #[arg(long, conflicts_with_all = ["output_format", "join_output", "raw_output0", "seq", "export", "github_output", "csv_table", "with_location", "in_place"])]
+    flatten: bool,
+
+    /// Read '.<path> = <value>' lines, as printed by '--flatten', instead of toml
+    #[arg(long, conflicts_with_all = ["with_location", "in_place"])]
+    unflatten: bool,
+
+    /// Print every result on its own line, prefixed with where it is in the toml as 'file:line:column'
+    #[arg(long, conflicts_with = "in_place")]
+    with_location: bool,
+}
+
+#[derive(Debug, Subcommand)]
+enum Command {
+    /// Deep merge toml files. Values from later files take precedence
+    Merge {
+        /// Toml files to merge, from lowest to highest precedence
+        #[arg(required = true, num_args = 2..)]
+        files: Vec<PathBuf>,
+
+        /// How to combine arrays found at the same key in more than one file
+        #[arg(short, long, value_enum, default_value_t = Arrays::Replace)]
+        arrays: Arrays,
+
+        /// Key used to match up tables when merging arrays with '--arrays merge-by-key'
+        #[arg(long, default_value = "name")]
+        merge_key: String,
+    },
+
+    /// Show the values added, removed and changed between two toml files
+    Diff {
+        /// Original toml file
+        old: PathBuf,
+
+        /// Changed toml file
+        new: PathBuf,
+
+        /// Also report tables with the same keys in a different order
+        #[arg(long)]
+        key_order: bool,
+
+        /// Also report values that are equal but written differently, such as '0xff' and '255'
+        #[arg(long)]
+        formatting: bool,
+
+        /// Output changes as json, one object per line
+        #[arg(short, long)]
+        json_output: bool,
+    },
+
+    /// Apply a patch of add/remove/replace/move/copy/test operations to a toml file.
+    /// Nothing is changed unless every operation succeeds
+    Patch {
+        /// Toml file to patch
+        file: PathBuf,
+
+        /// Patch file, with one '[[patch]]' table per operation
+        patch: PathBuf,
+
+        /// Write the patched toml back to the file, instead of printing it
+        #[arg(short, long)]
+        in_place: bool,
+    },
+
+    /// Render a template, replacing '{{ expr }}' with the results of toml-path expressions.
+    /// '{{ for expr }}', '{{ if expr }}', '{{ else }}' and '{{ end }}' loop over and test results
+    Render {
+        /// Template file
+        template: PathBuf,
+
+        /// Toml file to read values from. Toml content is read from stdin if omitted
+        file: Option<PathBuf>,
+    },
+
+    /// Rewrite a toml file in a canonical form, keeping its comments
+    Fmt {
+        /// Toml file to format
+        file: PathBuf,
+
+        /// Sort the keys of every table
+        #[arg(short, long)]
+        sort_keys: bool,
+
+        /// Sort the keys of only the table at this path, such as '.dependencies'. May be repeated
+        #[arg(long, value_name = "PATH")]
+        sort_table: Vec<TomlPath>,
+
+        /// Exit with an error if the file is not already formatted, instead of printing it
+        #[arg(long, conflicts_with = "in_place")]
+        check: bool,
+
+        /// Write the formatted toml back to the file, instead of printing it
+        #[arg(short, long)]
+        in_place: bool,
+    },
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Arrays {
+    /// Replace the earlier array
+    Replace,
+    /// Append to the earlier array
+    Append,
+    /// Append items not already in the earlier array
+    UniqueAppend,
+    /// Merge tables that share the same value at '--merge-key' and append the rest. Arrays of other values are replaced
+    MergeByKey,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Datetimes {
+    /// RFC 3339 strings, '"1979-05-27T07:32:00Z"'
+    String,
+    /// Tables of the type and RFC 3339 string, '{"type": "offset-datetime", "value": "..."}'
+    Tagged,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, ValueEnum)]
+enum Format {
+    /// Same as '--json-output'
+    Json,
+    /// Same as '--toml-output'
+    Toml,
+    /// Same as '--yaml-output'
+    Yaml,
+    /// 'PACKAGE__NAME='toml-path'' lines, as read by dotenv libraries and docker compose
+    Dotenv,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Keys {
+    /// 'PREFIX_PACKAGE_NAME'
+    Upper,
+    /// 'prefix_package_name'
+    Lower,
+    /// Keep the case of the prefix and keys
+    Preserve,
+}
+
+#[derive(Debug, Clone, Copy, ValueEnum)]
+enum Tables {
+    /// Follow the surrounding toml: dotted keys where they are already used, headers otherwise
+    Auto,
+    /// '[a.b]' headers
+    Header,
+    /// Dotted keys, 'a.b.c = 1'
+    Dotted,
+    /// Inline tables, 'a = { b = { c = 1 } }'
+    Inline,
+}
+
+/// Name of a GitHub Actions output for a result of the expression: the last key of its path
+fn output_name(expr: &Expr) -> Option<&str> {
+    let Expr::Path(path) = expr else {
+        return None;
+    };
+    path.parts().iter().rev().find_map(|op| match op {
+        Op::Name(name) => Some(name.as_str()),
+        _ => None,
+    })
+}
+
+/// Columns of the terminal stdout is written to, or None when it is not a terminal
+fn terminal_width() -> Option<usize> {
+    let stdout = io::stdout();
+    if !stdout.is_terminal() {
+        return None;
+    }
+    #[cfg(unix)]
+    if let Ok(size) = rustix::termios::tcgetwinsize(&stdout) {
+        if size.ws_col > 0 {
+            return Some(size.ws_col.into());
+        }
+    }
+    env::var("COLUMNS")
+        .ok()
+        .and_then(|columns| columns.parse().ok())
+}
+
+fn read_toml(file: &Path) -> Result<Value> {
+    let file = file.canonicalize()?;
+    debug!("Reading file: {}", file.display());
+    let contents = fs::read_to_string(file)?;
+    let toml: Value = toml::from_str(&contents)?;
+    Ok(toml)
+}
+
+fn read_document(file: &Path) -> Result<DocumentMut> {
+    let file = file.canonicalize()?;
+    debug!("Reading file: {}", file.display());
+    let contents = fs::read_to_string(file)?;
+    let doc: DocumentMut = contents.parse()?;
+    Ok(doc)
+}
+
+fn read_stdin() -> Result<String> {
+    let mut input = String::new();
+    let stdin = io::stdin();
+    for line in stdin.lock().lines() {
+        match line {
+            Ok(content) => {
+                input.push_str(&content);
+                input.push('\n');
+            }
+            Err(e) => {
+                bail!("Error reading stdin: {}", e);
+            }
+        }
+    }
+    Ok(input)
+}
+
+fn merge_files(files: &[PathBuf], arrays: Arrays, merge_key: String) -> Result<()> {
+    let strategy = match arrays {
+        Arrays::Replace => ArrayStrategy::Replace,
+        Arrays::Append => ArrayStrategy::Append,
+        Arrays::UniqueAppend => ArrayStrategy::UniqueAppend,
+        Arrays::MergeByKey => ArrayStrategy::MergeByKey(merge_key),
+    };
+    let mut merged = read_toml(&files[0])?;
+    for file in &files[1..] {
+        let overlay = read_toml(file)?;
+        merged = merge(&merged, &overlay, &strategy);
+    }
+    // Serialize the table itself rather than the value to keep the key order of the documents
+    let Some(merged) = merged.as_table() else {
+        unreachable!("toml documents are always tables");
+    };
+    print!("{}", toml::to_string(merged)?);
+    Ok(())
+}
+
+fn diff_files(old: &Path, new: &Path, options: DiffOptions, json_output: bool) -> Result<()> {
+    let old = read_document(old)?;
+    let new = read_document(new)?;
+    for change in diff(&old, &new, &options)? {
+        if json_output {
+            println!("{}", change.to_json());
+        } else {
+            println!("{}", change);
+        }
+    }
+    Ok(())
+}
+
+/// Create a temporary file next to a file, named after it, that no one else is writing to
+fn create_temporary(file: &Path, name: &OsStr) -> Result<(PathBuf, fs::File)> {
+    for attempt in 0.. {
+        let mut tmp_name = OsString::from(".");
+        tmp_name.push(name);
+        tmp_name.push(format!(".{}.{}.tmp", process::id(), attempt));
+        let tmp = file.with_file_name(tmp_name);
+        match fs::OpenOptions::new()
+            .write(true)
+            .create_new(true)
+            .open(&tmp)
+        {
+            Ok(created) => return Ok((tmp, created)),
+            Err(e) if e.kind() == io::ErrorKind::AlreadyExists => continue,
+            Err(e) => return Err(e.into()),
+        }
+    }
+    unreachable!("some attempt finds a free name")
+}
+
+/// Write to a temporary file in the same directory first, so the original file is never left half
+/// written, then move it over the original. The file keeps its permissions
+fn write_atomically(file: &Path, contents: &str) -> Result<()> {
+    let file = file.canonicalize()?;
+    let Some(name) = file.file_name() else {
+        bail!("Not a file: {}", file.display());
+    };
+    let permissions = fs::metadata(&file)?.permissions();
+    let (tmp, mut created) = create_temporary(&file, name)?;
+    let written = created
+        .write_all(contents.as_bytes())
+        .and_then(|_| created.sync_all())
+        .and_then(|_| fs::set_permissions(&tmp, permissions))
+        .and_then(|_| fs::rename(&tmp, &file));
+    if let Err(e) = written {
+        let _ = fs::remove_file(&tmp);
+        return Err(e.into());
+    }
+    Ok(())
+}
+
+fn patch_file(file: &Path, patch: &Path, in_place: bool) -> Result<()> {
+    let mut doc = read_document(file)?;
+    let patch: Patch = fs::read_to_string(patch)?.parse()?;
+    apply_patch(&mut doc, &patch)?;
+    if in_place {
+        write_atomically(file, &doc.to_string())?;
+    } else {
+        print!("{}", doc);
+    }
+    Ok(())
+}
+
+fn render_file(template: &Path, file: Option<&Path>) -> Result<()> {
+    let template = fs::read_to_string(template)?;
+    let doc = match file {
+        Some(file) => read_document(file)?,
+        None => read_stdin()?.parse()?,
+    };
+    print!("{}", render(&template, &doc)?);
+    Ok(())
+}
+
+fn fmt_file(file: &Path, options: FmtOptions, check: bool, in_place: bool) -> Result<()> {
+    let original = fs::read_to_string(file)?;
+    let mut doc: DocumentMut = original.parse()?;
+    format_document(&mut doc, &options)?;
+    let formatted = doc.to_string();
+    if check {
+        if formatted != original {
+            bail!("{} is not formatted", file.display());
+        }
+    } else if in_place {
+        write_atomically(file, &formatted)?;
+    } else {
+        print!("{}", formatted);
+    }
+    Ok(())
 }
 
 fn main() -> Result<()> {
@@ -40,40 +494,228 @@ fn main() -> Result<()> {
     let args = Cli::parse();
     debug!("args: {:?}", args);
 
+    if let Some(command) = args.command {
+        return match command {
+            Command::Merge {
+                files,
+                arrays,
+                merge_key,
+            } => merge_files(&files, arrays, merge_key),
+            Command::Diff {
+                old,
+                new,
+                key_order,
+                formatting,
+                json_output,
+            } => {
+                let options = DiffOptions {
+                    key_order,
+                    formatting,
+                };
+                diff_files(&old, &new, options, json_output)
+            }
+            Command::Patch {
+                file,
+                patch,
+                in_place,
+            } => patch_file(&file, &patch, in_place),
+            Command::Render { template, file } => render_file(&template, file.as_deref()),
+            Command::Fmt {
+                file,
+                sort_keys,
+                sort_table,
+                check,
+                in_place,
+            } => {
+                let options = FmtOptions {
+                    sort_keys,
+                    sort_tables: sort_table,
+                };
+                fmt_file(&file, options, check, in_place)
+            }
+        };
+    }
+    let Some(path) = args.path else {
+        unreachable!("clap requires a path when no subcommand is given");
+    };
+
+    let color_output = match (args.color_output, args.monochrome_output) {
+        (true, _) => true,
+        (_, true) => false,
+        _ => {
+            io::stdout().is_terminal()
+                && env::var_os("NO_COLOR").is_none_or(|no_color| no_color.is_empty())
+        }
+    };
+    let colors = match env::var("TOML_PATH_COLORS") {
+        Ok(colors) if color_output => match Colors::from_str(&colors) {
+            Ok(colors) => colors,
+            Err(e) => bail!("Invalid $TOML_PATH_COLORS: {}", e),
+        },
+        _ => Colors::default(),
+    };
     let settings = Settings::builder()
-        .raw_output(args.raw_output)
+        .output_format(match () {
+            _ if args.raw_output || args.raw_output0 || args.join_output => OutputFormat::Raw,
+            _ if args.json_output || args.format == Some(Format::Json) => OutputFormat::Json,
+            _ if args.toml_output || args.format == Some(Format::Toml) => OutputFormat::Toml,
+            _ if args.yaml_output || args.format == Some(Format::Yaml) => OutputFormat::Yaml,
+            _ if args.format == Some(Format::Dotenv) => OutputFormat::Dotenv,
+            _ => OutputFormat::Quoted,
+        })
         .compact_output(args.compact_output)
-        .json_output(args.json_output)
-        .build();
+        .indent(args.indent)
+        .tab(args.tab)
+        .sort_keys(args.sort_keys)
+        .max_width(args.max_width)
+        .original_integers(args.original_integers)
+        .ascii_output(args.ascii_output)
+        .color_output(color_output)
+        .colors(colors)
+        .datetimes(match args.json_datetimes {
+            Datetimes::String => DatetimeFormat::String,
+            Datetimes::Tagged => DatetimeFormat::Tagged,
+        })
+        .delimiter(match () {
+            _ if args.raw_output0 => Delimiter::Nul,
+            _ if args.join_output => Delimiter::None,
+            _ if args.seq => Delimiter::RecordSeparator,
+            _ => Delimiter::Newline,
+        })
+        .build()?;
     debug!("settings: {:?}", settings);
 
-    let input = match args.file {
+    let source = match &args.file {
         Some(file) => {
             let file = file.canonicalize()?;
-            let contents = fs::read_to_string(file)?;
-            contents
+            debug!("Reading file: {}", file.display());
+            fs::read_to_string(file)?
         }
-        None => {
-            // Read toml from stdin
-            let mut input = String::new();
-            let stdin = io::stdin();
-            for line in stdin.lock().lines() {
-                match line {
-                    Ok(content) => {
-                        input.push_str(&content);
-                    }
-                    Err(e) => {
-                        bail!("Error reading stdin: {}", e);
-                    }
-                }
+        None => read_stdin()?,
+    };
+    let source = if args.unflatten {
+        toml::to_string(&unflatten(&source)?)?
+    } else {
+        source
+    };
+    if args.with_location {
+        let Expr::Path(path) = &path else {
+            bail!("--with-location only works with paths, not '{}'", path);
+        };
+        let name = match &args.file {
+            Some(file) => file.display().to_string(),
+            None => String::from("<stdin>"),
+        };
+        let results: Vec<String> = locate(&source, path)?
+            .iter()
+            .map(|found| {
+                let value = format_value(&found.value, &settings)?;
+                Ok(format!(
+                    "{}:{}:{}: {}",
+                    name, found.line, found.column, value
+                ))
+            })
+            .collect::<Result<_>>()?;
+        print!("{}", join_results(&results, &settings)?);
+        return Ok(());
+    }
+    let mut doc: DocumentMut = source.parse()?;
+    if path.edits_document() {
+        let style = match args.table_style {
+            Tables::Auto => TableStyle::Auto,
+            Tables::Header => TableStyle::Header,
+            Tables::Dotted => TableStyle::Dotted,
+            Tables::Inline => TableStyle::Inline,
+        };
+        eval_edit(&mut doc, &path, style)?;
+        match args.file {
+            Some(file) if args.in_place => write_atomically(&file, &doc.to_string())?,
+            _ => print!("{}", doc),
+        }
+        return Ok(());
+    }
+    if args.in_place {
+        bail!(
+            "'{}' does not edit the toml, so cannot be used with --in-place",
+            path
+        );
+    }
+
+    if let Some(prefix) = args.export {
+        let keys = match args.export_keys {
+            Keys::Upper => KeyCase::Upper,
+            Keys::Lower => KeyCase::Lower,
+            Keys::Preserve => KeyCase::Preserve,
+        };
+        let options = ExportOptions {
+            prefix,
+            keys,
+            ..ExportOptions::default()
+        };
+        for value in eval_results(&doc, &path)? {
+            println!("{}", export(&value, &options)?);
+        }
+        return Ok(());
+    }
+
+    if args.csv_table {
+        for value in eval_results(&doc, &path)? {
+            println!("{}", csv_table(&value)?);
+        }
+        return Ok(());
+    }
+
+    if args.table {
+        let max_width = args.max_width.or_else(terminal_width);
+        for value in eval_results(&doc, &path)? {
+            println!("{}", text_table(&value, max_width)?);
+        }
+        return Ok(());
+    }
+
+    if args.flatten {
+        for value in eval_results(&doc, &path)? {
+            for line in flatten(&value) {
+                println!("{}", line);
             }
-            input
         }
-    };
+        return Ok(());
+    }
+
+    if args.github_output {
+        let Some(file) = env::var_os("GITHUB_OUTPUT") else {
+            bail!("--github-output needs $GITHUB_OUTPUT to name the file to append to");
+        };
+        let mut lines = String::new();
+        let results = eval_results(&doc, &path)?;
+        if results.len() > 1 && !results.iter().all(Value::is_table) {
+            // GitHub keeps only the last of several outputs with the same name
+            bail!(
+                "'{}' gives {} results, which would all be written to the same output",
+                path,
+                results.len()
+            );
+        }
+        for value in results {
+            let name = output_name(&path);
+            if name.is_none() && !value.is_table() {
+                bail!(
+                    "Cannot name the output of '{}', as it does not end in a key",
+                    path
+                );
+            }
+            lines.push_str(&github_output(&value, name.unwrap_or_default())?);
+            lines.push('\n');
+        }
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(file)?;
+        file.write_all(lines.as_bytes())?;
+        return Ok(());
+    }
 
-    let toml: Value = toml::from_str(&input)?;
-    let result = get(&toml, &args.path, &settings)?;
-    println!("{}", result);
+    print!("{}", eval_document(&doc, &path, &settings)?);
 
     Ok(())
 }
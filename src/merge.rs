@@ -0,0 +1,213 @@
+use toml::{Table, Value};
+
+/// How [merge] combines two arrays found at the same key
+#[derive(Debug, Eq, PartialEq, Clone, Default)]
+pub enum ArrayStrategy {
+    /// The overlay array replaces the base array
+    #[default]
+    Replace,
+
+    /// Items of the overlay array are appended to the base array
+    Append,
+
+    /// Items of the overlay array are appended to the base array, skipping items the base array already contains
+    UniqueAppend,
+
+    /// Tables in the two arrays that share the same value at the given key are merged together.
+    /// Every other overlay table is appended. Arrays that are not arrays of tables, such as
+    /// `features = ["tls"]`, are replaced by the overlay array, at any depth.
+    /// Useful for arrays of tables like `[[bin]]`, which are usually keyed by `name`
+    MergeByKey(String),
+}
+
+fn merge_arrays(base: &[Value], overlay: &[Value], strategy: &ArrayStrategy) -> Vec<Value> {
+    match strategy {
+        ArrayStrategy::Replace => overlay.to_vec(),
+        ArrayStrategy::Append => base.iter().chain(overlay).cloned().collect(),
+        ArrayStrategy::UniqueAppend => {
+            let mut merged = base.to_vec();
+            for item in overlay {
+                if !merged.contains(item) {
+                    merged.push(item.clone());
+                }
+            }
+            merged
+        }
+        ArrayStrategy::MergeByKey(_) if !base.iter().chain(overlay).all(Value::is_table) => {
+            overlay.to_vec()
+        }
+        ArrayStrategy::MergeByKey(key) => {
+            let mut merged = base.to_vec();
+            for item in overlay {
+                let id = item.as_table().and_then(|table| table.get(key));
+                let existing = id.and_then(|id| {
+                    merged.iter_mut().find(|candidate| {
+                        candidate.as_table().and_then(|table| table.get(key)) == Some(id)
+                    })
+                });
+                match existing {
+                    Some(existing) => *existing = merge(existing, item, strategy),
+                    None => merged.push(item.clone()),
+                }
+            }
+            merged
+        }
+    }
+}
+
+fn merge_tables(base: &Table, overlay: &Table, strategy: &ArrayStrategy) -> Table {
+    let mut merged = base.clone();
+    for (key, value) in overlay {
+        let value = match merged.get(key) {
+            Some(existing) => merge(existing, value, strategy),
+            None => value.clone(),
+        };
+        merged.insert(key.clone(), value);
+    }
+    merged
+}
+
+/// Deep merge two toml values.
+/// Tables are merged recursively, arrays are combined according to the [ArrayStrategy],
+/// and any other value from the overlay replaces the value from the base.
+pub fn merge(base: &Value, overlay: &Value, strategy: &ArrayStrategy) -> Value {
+    match (base, overlay) {
+        (Value::Table(base), Value::Table(overlay)) => {
+            Value::Table(merge_tables(base, overlay, strategy))
+        }
+        (Value::Array(base), Value::Array(overlay)) => {
+            Value::Array(merge_arrays(base, overlay, strategy))
+        }
+        (_, overlay) => overlay.clone(),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn toml(s: &str) -> Value {
+        Value::Table(toml::from_str(s).unwrap())
+    }
+
+    #[test]
+    fn test_merge_nested_tables() {
+        let base = toml("[a]\nx = 1\ny = 2\n[b]\nz = 3");
+        let overlay = toml("[a]\ny = 20\nw = 40");
+        let expected = toml("[a]\nx = 1\ny = 20\nw = 40\n[b]\nz = 3");
+        let result = merge(&base, &overlay, &ArrayStrategy::Replace);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_merge_table_replaced_by_scalar() {
+        let base = toml("a = { x = 1 }");
+        let overlay = toml("a = 1");
+        let result = merge(&base, &overlay, &ArrayStrategy::Replace);
+        assert_eq!(overlay, result);
+    }
+
+    #[test]
+    fn test_merge_arrays_replace() {
+        let base = toml("a = [1, 2]");
+        let overlay = toml("a = [2, 3]");
+        let result = merge(&base, &overlay, &ArrayStrategy::Replace);
+        assert_eq!(overlay, result);
+    }
+
+    #[test]
+    fn test_merge_arrays_append() {
+        let base = toml("a = [1, 2]");
+        let overlay = toml("a = [2, 3]");
+        let expected = toml("a = [1, 2, 2, 3]");
+        let result = merge(&base, &overlay, &ArrayStrategy::Append);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_merge_arrays_unique_append() {
+        let base = toml("a = [1, 2]");
+        let overlay = toml("a = [2, 3]");
+        let expected = toml("a = [1, 2, 3]");
+        let result = merge(&base, &overlay, &ArrayStrategy::UniqueAppend);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_merge_arrays_by_key() {
+        let base = toml(
+            r#"
+            [[bin]]
+            name = "foo"
+            path = "src/foo.rs"
+
+            [[bin]]
+            name = "bar"
+            path = "src/bar.rs"
+            "#,
+        );
+        let overlay = toml(
+            r#"
+            [[bin]]
+            name = "bar"
+            test = false
+
+            [[bin]]
+            name = "baz"
+            "#,
+        );
+        let expected = toml(
+            r#"
+            [[bin]]
+            name = "foo"
+            path = "src/foo.rs"
+
+            [[bin]]
+            name = "bar"
+            path = "src/bar.rs"
+            test = false
+
+            [[bin]]
+            name = "baz"
+            "#,
+        );
+        let strategy = ArrayStrategy::MergeByKey(String::from("name"));
+        let result = merge(&base, &overlay, &strategy);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_merge_arrays_by_key_replaces_other_arrays() {
+        let base = toml(
+            r#"
+            features = ["a"]
+
+            [[bin]]
+            name = "cli"
+            required-features = ["a"]
+            "#,
+        );
+        let overlay = toml(
+            r#"
+            features = ["b"]
+
+            [[bin]]
+            name = "cli"
+            required-features = ["a", "b"]
+            "#,
+        );
+        let expected = toml(
+            r#"
+            features = ["b"]
+
+            [[bin]]
+            name = "cli"
+            required-features = ["a", "b"]
+            "#,
+        );
+        let strategy = ArrayStrategy::MergeByKey(String::from("name"));
+        let result = merge(&base, &overlay, &strategy);
+        assert_eq!(expected, result);
+    }
+}
@@ -0,0 +1,265 @@
+use crate::edit::{
+    get_item, insert_item, item_to_value, move_item, remove_item, replace_item, value_to_item,
+    TableStyle,
+};
+use crate::toml_path::TomlPath;
+use eyre::Result;
+use eyre::{bail, WrapErr};
+use serde::Deserialize;
+use std::fmt;
+use std::str::FromStr;
+use toml::Value;
+use toml_edit::DocumentMut;
+
+/// A single operation of a [Patch]. Modelled on RFC 6902 (JSON Patch), with toml paths as addresses
+#[derive(Debug, PartialEq, Clone, Deserialize)]
+#[serde(tag = "op", rename_all = "lowercase", deny_unknown_fields)]
+pub enum Operation {
+    /// Insert a value. The parent of the path must exist. Existing values at a key are replaced,
+    /// while existing array items are shifted back. Negative indexes count back from the end of the
+    /// array after the insertion, so an index of '-1' appends
+    Add {
+        path: TomlPath,
+        value: Value,
+        #[serde(default)]
+        style: TableStyle,
+    },
+
+    /// Remove the value at the path
+    Remove { path: TomlPath },
+
+    /// Replace the existing value at the path, keeping its comments
+    Replace {
+        path: TomlPath,
+        value: Value,
+        #[serde(default)]
+        style: TableStyle,
+    },
+
+    /// Remove the value at `from` and add it at `path`
+    Move { from: TomlPath, path: TomlPath },
+
+    /// Add a copy of the value at `from` at `path`
+    Copy { from: TomlPath, path: TomlPath },
+
+    /// Check that the value at the path equals the given value
+    Test { path: TomlPath, value: Value },
+}
+
+impl fmt::Display for Operation {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Operation::Add { path, .. } => write!(f, "add {}", path),
+            Operation::Remove { path } => write!(f, "remove {}", path),
+            Operation::Replace { path, .. } => write!(f, "replace {}", path),
+            Operation::Move { from, path } => write!(f, "move {} to {}", from, path),
+            Operation::Copy { from, path } => write!(f, "copy {} to {}", from, path),
+            Operation::Test { path, .. } => write!(f, "test {}", path),
+        }
+    }
+}
+
+/// List of operations to apply to a toml document with [apply_patch].
+/// Impls std::str::FromStr to read patches written as toml, with one `[[patch]]` table per operation:
+/// ```toml
+/// [[patch]]
+/// op = "test"
+/// path = ".package.version"
+/// value = "0.1.0"
+///
+/// [[patch]]
+/// op = "replace"
+/// path = ".package.version"
+/// value = "0.2.0"
+/// ```
+/// `add` and `replace` take an optional `style` of "auto", "header", "dotted" or "inline", choosing how
+/// tables and arrays of tables in the value are written. See [TableStyle]
+#[derive(Debug, PartialEq, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct Patch {
+    #[serde(rename = "patch")]
+    pub operations: Vec<Operation>,
+}
+
+impl FromStr for Patch {
+    type Err = toml::de::Error;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        toml::from_str(s)
+    }
+}
+
+fn apply_operation(doc: &mut DocumentMut, operation: &Operation) -> Result<()> {
+    match operation {
+        Operation::Add { path, value, style } => {
+            insert_item(doc, path, value_to_item(path, value, *style)?)
+        }
+        Operation::Remove { path } => remove_item(doc, path).map(|_| ()),
+        Operation::Replace { path, value, style } => {
+            replace_item(doc, path, value_to_item(path, value, *style)?).map(|_| ())
+        }
+        Operation::Move { from, path } => move_item(doc, from, path),
+        Operation::Copy { from, path } => {
+            let item = get_item(doc, from)?.clone();
+            insert_item(doc, path, item)
+        }
+        Operation::Test { path, value } => {
+            let Some(actual) = item_to_value(get_item(doc, path)?) else {
+                bail!("Nothing found at '{}'", path);
+            };
+            if actual != *value {
+                bail!("Expected {} at '{}', found {}", value, path, actual);
+            }
+            Ok(())
+        }
+    }
+}
+
+/// Apply a patch to a toml document, preserving its formatting and comments.
+/// Either every operation succeeds, or the document is left untouched
+pub fn apply_patch(doc: &mut DocumentMut, patch: &Patch) -> Result<()> {
+    let mut patched = doc.clone();
+    for (i, operation) in patch.operations.iter().enumerate() {
+        apply_operation(&mut patched, operation)
+            .wrap_err_with(|| format!("Patch operation {} ({}) failed", i + 1, operation))?;
+    }
+    *doc = patched;
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const DOC: &str = r#"[package]
+name = "app" # keep me
+version = "0.1.0"
+
+[dev_dependencies]
+pretty_assertions = "1"
+"#;
+
+    fn patch(patch: &str) -> (Result<()>, String) {
+        let mut doc = DocumentMut::from_str(DOC).unwrap();
+        let patch = Patch::from_str(patch).unwrap();
+        let result = apply_patch(&mut doc, &patch);
+        (result, doc.to_string())
+    }
+
+    #[test]
+    fn test_patch_parse() {
+        let input = r#"
+            [[patch]]
+            op = "move"
+            from = ".a"
+            path = ".b"
+
+            [[patch]]
+            op = "test"
+            path = ".b[0]"
+            value = 1979-05-27
+        "#;
+        let expected = Patch {
+            operations: vec![
+                Operation::Move {
+                    from: TomlPath::from_str(".a").unwrap(),
+                    path: TomlPath::from_str(".b").unwrap(),
+                },
+                Operation::Test {
+                    path: TomlPath::from_str(".b[0]").unwrap(),
+                    value: Value::Datetime("1979-05-27".parse().unwrap()),
+                },
+            ],
+        };
+        assert_eq!(Ok(expected), Patch::from_str(input));
+    }
+
+    #[test]
+    fn test_patch_parse_unknown_op() {
+        let input = "[[patch]]\nop = \"frobnicate\"\npath = \".a\"";
+        assert!(Patch::from_str(input).is_err());
+    }
+
+    #[test]
+    fn test_patch_parse_unknown_fields() {
+        let input = "[[op]]\nop = \"remove\"\npath = \".a\"";
+        assert!(Patch::from_str(input).is_err());
+        let input = "[[patch]]\nop = \"remove\"\npath = \".a\"\nvalu = 1";
+        assert!(Patch::from_str(input).is_err());
+        assert!(Patch::from_str("").is_err());
+    }
+
+    #[test]
+    fn test_patch_apply() {
+        let (result, doc) = patch(
+            r#"
+            [[patch]]
+            op = "test"
+            path = ".package.version"
+            value = "0.1.0"
+
+            [[patch]]
+            op = "replace"
+            path = ".package.version"
+            value = "0.2.0"
+
+            [[patch]]
+            op = "add"
+            path = ".package.keywords"
+            value = ["cli"]
+
+            [[patch]]
+            op = "copy"
+            from = ".package.name"
+            path = ".package.keywords[-1]"
+
+            [[patch]]
+            op = "move"
+            from = ".dev_dependencies"
+            path = ".dev-dependencies"
+            "#,
+        );
+        let expected = r#"[package]
+name = "app" # keep me
+version = "0.2.0"
+keywords = ["cli", "app"]
+
+[dev-dependencies]
+pretty_assertions = "1"
+"#;
+        result.unwrap();
+        assert_eq!(expected, doc);
+    }
+
+    #[test]
+    fn test_patch_failed_test_is_atomic() {
+        let (result, doc) = patch(
+            r#"
+            [[patch]]
+            op = "remove"
+            path = ".package.name"
+
+            [[patch]]
+            op = "test"
+            path = ".package.version"
+            value = "9.9.9"
+            "#,
+        );
+        assert!(result.is_err());
+        assert_eq!(DOC, doc);
+    }
+
+    #[test]
+    fn test_patch_move_into_itself() {
+        let (result, _) = patch(
+            r#"
+            [[patch]]
+            op = "move"
+            from = ".package"
+            path = ".package.inner"
+            "#,
+        );
+        assert!(result.is_err());
+    }
+}
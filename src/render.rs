@@ -0,0 +1,371 @@
+use crate::edit::to_value;
+use crate::eval::evaluate;
+use crate::expr::Expr;
+use crate::string_formats::text;
+use crate::traverse::PathError;
+use eyre::Result;
+use eyre::{bail, eyre, WrapErr};
+use std::str::FromStr;
+use toml::{Table, Value};
+use toml_edit::DocumentMut;
+
+/// What is inside of a `{{ ... }}`
+enum Tag {
+    /// `{{ .package.name }}`
+    Expr(Expr),
+    /// `{{ for .bin }}`
+    For(Expr),
+    /// `{{ if .package.publish }}`
+    If(Expr),
+    Else,
+    End,
+}
+
+enum Token {
+    Text(String),
+    Tag { tag: Tag, line: usize },
+}
+
+enum Node {
+    Text(String),
+    Expr {
+        expr: Expr,
+        line: usize,
+    },
+    For {
+        expr: Expr,
+        line: usize,
+        body: Vec<Node>,
+    },
+    If {
+        expr: Expr,
+        line: usize,
+        then: Vec<Node>,
+        otherwise: Vec<Node>,
+    },
+}
+
+fn tag(content: &str, line: usize) -> Result<Tag> {
+    let parse = |s: &str| {
+        Expr::from_str(s.trim())
+            .map_err(|_| eyre!("Line {}: invalid expression '{}'", line, s.trim()))
+    };
+    let content = content.trim();
+    match content {
+        "else" => Ok(Tag::Else),
+        "end" => Ok(Tag::End),
+        _ => match (content.strip_prefix("for "), content.strip_prefix("if ")) {
+            (Some(expr), _) => Ok(Tag::For(parse(expr)?)),
+            (_, Some(expr)) => Ok(Tag::If(parse(expr)?)),
+            _ => Ok(Tag::Expr(parse(content)?)),
+        },
+    }
+}
+
+/// Split a template into text and tags. Block tags, such as `{{ end }}`, that are alone on their
+/// line take the whole line with them, so they leave no blank lines behind. `\{{` is a literal
+/// `{{`, such as for the `${{ ... }}` of GitHub workflows
+fn tokenize(template: &str) -> Result<Vec<Token>> {
+    let mut tokens = Vec::new();
+    let mut text = String::new();
+    let mut pos = 0;
+    while let Some(offset) = template[pos..].find("{{") {
+        let start = pos + offset;
+        if template[..start].ends_with('\\') {
+            text.push_str(&template[pos..start - 1]);
+            text.push_str("{{");
+            pos = start + 2;
+            continue;
+        }
+        let line = template[..start].matches('\n').count() + 1;
+        let Some(length) = template[start..].find("}}") else {
+            bail!("Line {}: '{{{{' is never closed with '}}}}'", line);
+        };
+        let end = start + length + 2;
+        let tag = tag(&template[start + 2..end - 2], line)?;
+        text.push_str(&template[pos..start]);
+        pos = end;
+        if !matches!(tag, Tag::Expr(_)) {
+            let line_start = template[..start].rfind('\n').map_or(0, |i| i + 1);
+            let before = &template[line_start..start];
+            let line_end = template[end..]
+                .find('\n')
+                .map_or(template.len(), |i| end + i + 1);
+            if before.trim().is_empty() && template[end..line_end].trim().is_empty() {
+                text.truncate(text.len() - before.len());
+                pos = line_end;
+            }
+        }
+        tokens.push(Token::Text(std::mem::take(&mut text)));
+        tokens.push(Token::Tag { tag, line });
+    }
+    text.push_str(&template[pos..]);
+    tokens.push(Token::Text(text));
+    Ok(tokens)
+}
+
+/// A block being parsed, with the nodes found in it so far
+struct Block {
+    /// The `for` or `if` tag that opened it, or None for the template itself
+    open: Option<(Tag, usize)>,
+    nodes: Vec<Node>,
+    /// Nodes before the `{{ else }}` of an if block
+    then: Option<Vec<Node>>,
+}
+
+fn parse(template: &str) -> Result<Vec<Node>> {
+    let mut blocks = vec![Block {
+        open: None,
+        nodes: Vec::new(),
+        then: None,
+    }];
+    for token in tokenize(template)? {
+        let node = match token {
+            Token::Text(text) => Node::Text(text),
+            Token::Tag {
+                tag: Tag::Expr(expr),
+                line,
+            } => Node::Expr { expr, line },
+            Token::Tag {
+                tag: tag @ (Tag::For(_) | Tag::If(_)),
+                line,
+            } => {
+                blocks.push(Block {
+                    open: Some((tag, line)),
+                    nodes: Vec::new(),
+                    then: None,
+                });
+                continue;
+            }
+            Token::Tag {
+                tag: Tag::Else,
+                line,
+            } => {
+                let block = blocks
+                    .last_mut()
+                    .expect("the block of the template itself stays open");
+                if !matches!(block.open, Some((Tag::If(_), _))) || block.then.is_some() {
+                    bail!("Line {}: '{{{{ else }}}}' outside of an if block", line);
+                }
+                block.then = Some(std::mem::take(&mut block.nodes));
+                continue;
+            }
+            Token::Tag {
+                tag: Tag::End,
+                line,
+            } => {
+                let block = blocks
+                    .pop()
+                    .expect("the block of the template itself stays open");
+                match block.open {
+                    Some((Tag::For(expr), line)) => Node::For {
+                        expr,
+                        line,
+                        body: block.nodes,
+                    },
+                    Some((Tag::If(expr), line)) => match block.then {
+                        Some(then) => Node::If {
+                            expr,
+                            line,
+                            then,
+                            otherwise: block.nodes,
+                        },
+                        None => Node::If {
+                            expr,
+                            line,
+                            then: block.nodes,
+                            otherwise: Vec::new(),
+                        },
+                    },
+                    _ => bail!("Line {}: '{{{{ end }}}}' without a for or if block", line),
+                }
+            }
+        };
+        blocks
+            .last_mut()
+            .expect("the block of the template itself stays open")
+            .nodes
+            .push(node);
+    }
+    let template = blocks
+        .pop()
+        .expect("the block of the template itself stays open");
+    if let Some((_, line)) = template.open {
+        bail!("Line {}: block is never closed with '{{{{ end }}}}'", line);
+    }
+    Ok(template.nodes)
+}
+
+/// What a for block loops over: the items of an array, or a `{ key = ..., value = ... }` table per
+/// entry of a table
+fn items(value: Value, line: usize) -> Result<Vec<Value>> {
+    match value {
+        Value::Array(items) => Ok(items),
+        Value::Table(table) => Ok(table
+            .into_iter()
+            .map(|(key, value)| {
+                let mut entry = Table::new();
+                entry.insert(String::from("key"), Value::String(key));
+                entry.insert(String::from("value"), value);
+                Value::Table(entry)
+            })
+            .collect()),
+        _ => bail!(
+            "Line {}: cannot loop over {}, expected an array or a table",
+            line,
+            value.type_str()
+        ),
+    }
+}
+
+/// Evaluate the expression of a tag against the input, naming the tag in errors
+fn eval(expr: &Expr, line: usize, input: &Value, doc: Option<&DocumentMut>) -> Result<Vec<Value>> {
+    evaluate(input, expr, doc)
+        .wrap_err_with(|| format!("Line {}: failed to evaluate '{}'", line, expr))
+}
+
+fn render_nodes(
+    nodes: &[Node],
+    input: &Value,
+    doc: Option<&DocumentMut>,
+    output: &mut String,
+) -> Result<()> {
+    for node in nodes {
+        match node {
+            Node::Text(text) => output.push_str(text),
+            Node::Expr { expr, line } => {
+                let results: Vec<String> =
+                    eval(expr, *line, input, doc)?.iter().map(text).collect();
+                output.push_str(&results.join("\n"));
+            }
+            Node::For { expr, line, body } => {
+                for value in eval(expr, *line, input, doc)? {
+                    // Builtins that read the document, such as `comments(...)`, only apply to the root
+                    for item in items(value, *line)? {
+                        render_nodes(body, &item, None, output)?;
+                    }
+                }
+            }
+            Node::If {
+                expr,
+                line,
+                then,
+                otherwise,
+            } => {
+                let results = match eval(expr, *line, input, doc) {
+                    Ok(results) => results,
+                    // A missing key is false, as jq reads it as null. Other errors, such as
+                    // indexing a string, are still errors
+                    Err(e) if matches!(e.downcast_ref(), Some(PathError::MissingKey { .. })) => {
+                        Vec::new()
+                    }
+                    Err(e) => return Err(e),
+                };
+                let condition = results
+                    .iter()
+                    .any(|value| !matches!(value, Value::Boolean(false)));
+                let nodes = if condition { then } else { otherwise };
+                render_nodes(nodes, input, doc, output)?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Render a template against a format preserving document. `{{ expr }}` is replaced by the results
+/// of a toml-path expression, one per line, with strings written as they are and other values as
+/// inline toml. `{{ for expr }}...{{ end }}` repeats its body for every item of an array, or every
+/// `{ key = ..., value = ... }` entry of a table, with `.` as the item. `{{ if expr }}...{{ end }}`,
+/// with an optional `{{ else }}`, is false when every result is `false`, and for paths to a
+/// missing key. `\{{` writes a literal `{{`
+pub fn render(template: &str, doc: &DocumentMut) -> Result<String> {
+    let nodes = parse(template)?;
+    let mut output = String::new();
+    render_nodes(&nodes, &to_value(doc), Some(doc), &mut output)?;
+    Ok(output)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TOML: &str = r#"
+[package]
+name = "app"
+publish = false
+keywords = ["cli", "toml"]
+
+[dependencies]
+serde = "1.0"
+clap = "4.5"
+"#;
+
+    fn render_str(template: &str) -> Result<String> {
+        let doc: DocumentMut = TOML.parse().unwrap();
+        render(template, &doc)
+    }
+
+    #[test]
+    fn test_render() {
+        let template = "FROM rust\nLABEL name={{ .package.name }} tags={{.package.keywords}}\n";
+        let expected = "FROM rust\nLABEL name=app tags=[\"cli\", \"toml\"]\n";
+        assert_eq!(expected, render_str(template).unwrap());
+    }
+
+    #[test]
+    fn test_render_for() {
+        let template = "keywords:\n{{ for .package.keywords }}\n- {{ . }}\n{{ end }}\n{{ for .dependencies }}{{ .key }}@{{ .value }} {{ end }}";
+        let expected = "keywords:\n- cli\n- toml\nserde@1.0 clap@4.5 ";
+        assert_eq!(expected, render_str(template).unwrap());
+    }
+
+    #[test]
+    fn test_render_if() {
+        let template = "{{ if .package.publish }}publish{{ else }}private{{ end }}\n  {{ if .package.license }}\n{{ .package.license }}\n  {{ end }}\ndone";
+        let expected = "private\ndone";
+        assert_eq!(expected, render_str(template).unwrap());
+    }
+
+    #[test]
+    fn test_render_escape() {
+        let template = "run: echo ${{ .package.name }} $\\{{ github.sha }}";
+        let expected = "run: echo $app ${{ github.sha }}";
+        assert_eq!(expected, render_str(template).unwrap());
+    }
+
+    #[test]
+    fn test_render_errors() {
+        let error = |template: &str| render_str(template).unwrap_err().to_string();
+        assert_eq!(
+            "Line 2: failed to evaluate '.package.nmae'",
+            error("name:\n{{ .package.nmae }}")
+        );
+        assert_eq!(
+            "Line 1: '{{' is never closed with '}}'",
+            error("{{ .package.name")
+        );
+        assert_eq!(
+            "Line 3: block is never closed with '{{ end }}'",
+            error("a\n\n{{ for .package.keywords }}")
+        );
+        assert_eq!(
+            "Line 1: '{{ end }}' without a for or if block",
+            error("{{ end }}")
+        );
+        assert_eq!(
+            "Line 1: '{{ else }}' outside of an if block",
+            error("{{ for .package.keywords }}{{ else }}{{ end }}")
+        );
+        assert_eq!(
+            "Line 1: cannot loop over string, expected an array or a table",
+            error("{{ for .package.name }}{{ end }}")
+        );
+        assert_eq!("Line 1: invalid expression '.['", error("{{ .[ }}"));
+        // Only a missing key is false, not indexing something of the wrong type
+        assert_eq!(
+            "Line 1: failed to evaluate '.package.name[0]'",
+            error("{{ if .package.name[0] }}{{ end }}")
+        );
+    }
+}
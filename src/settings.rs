@@ -1,8 +1,69 @@
+use crate::colors::Colors;
+use crate::format::{Dotenv, Formatter, Json, Raw, Toml, Yaml};
 use log::debug;
+use std::collections::HashMap;
+use thiserror::Error;
 
-const RAW_OUTPUT_DEFAULT: bool = true;
-const JSON_OUTPUT_DEFAULT: bool = false;
 const COMPACT_OUTPUT_DEFAULT: bool = false;
+const INDENT_DEFAULT: usize = 2;
+const ASCII_OUTPUT_DEFAULT: bool = false;
+const COLOR_OUTPUT_DEFAULT: bool = false;
+const TAB_DEFAULT: bool = false;
+const SORT_KEYS_DEFAULT: bool = false;
+const ORIGINAL_INTEGERS_DEFAULT: bool = false;
+
+/// How results are written
+#[derive(Debug, Default, Clone, PartialEq, Eq)]
+pub enum OutputFormat {
+    /// Tables as toml sections and other values inline, with strings written as they are
+    #[default]
+    Raw,
+    /// Like [OutputFormat::Raw], with strings in quotes
+    Quoted,
+    Json,
+    /// Toml that can be parsed back: tables as whole documents, other values as toml values
+    Toml,
+    /// Yaml, with multi-line strings as literal blocks
+    Yaml,
+    /// Tables as dotenv `KEY=value` lines
+    Dotenv,
+    /// A format registered under this name with [SettingsBuilder::formatter]
+    Custom(String),
+}
+
+#[derive(Error, Debug, Eq, PartialEq)]
+pub enum SettingsError {
+    #[error("No formatter is registered for output format '{0}'")]
+    UnknownFormat(String),
+}
+
+/// How datetimes are written in json output, which has no datetime type of its own
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum DatetimeFormat {
+    /// RFC 3339 strings, such as `"1979-05-27T07:32:00Z"`. Local dates and times use the partial
+    /// forms, `"1979-05-27"` and `"07:32:00"`
+    #[default]
+    String,
+    /// Tables of the toml type and the RFC 3339 string, such as
+    /// `{"type": "offset-datetime", "value": "1979-05-27T07:32:00Z"}`. The types are
+    /// `offset-datetime`, `local-datetime`, `local-date` and `local-time`
+    Tagged,
+}
+
+/// What is written after each result when a query has several
+#[derive(Debug, Default, Clone, Copy, PartialEq, Eq)]
+pub enum Delimiter {
+    /// A newline after each result
+    #[default]
+    Newline,
+    /// A NUL byte after each result, for `xargs -0`. Results may not contain NUL themselves
+    Nul,
+    /// Nothing, joining the results together
+    None,
+    /// RFC 7464 json text sequences: an ASCII record separator before each result and a newline
+    /// after it
+    RecordSeparator,
+}
 
 fn spacing(compact: bool) -> String {
     if compact {
@@ -24,35 +85,89 @@ fn separator(compact: bool, json: bool) -> String {
     s
 }
 
-/// TODO: doc comments
+/// How results are formatted, built with [Settings::builder]
 #[derive(Debug)]
 pub struct Settings {
-    pub raw_output: bool,
+    pub format: OutputFormat,
     pub compact_output: bool,
-    pub json_output: bool,
     pub separator: String,
     pub spacing: String,
+    /// Spaces per level of indentation when pretty printing
+    pub indent: usize,
+    /// Indent with a tab per level instead of spaces
+    pub tab: bool,
+    /// Write the keys of every table in sorted order
+    pub sort_keys: bool,
+    /// Widest line nested tables and arrays may be written on before they are expanded over
+    /// multiple lines. The default output falls back to 80, while json and toml output are only
+    /// fitted to a width when one is given
+    pub max_width: Option<usize>,
+    /// Write integers read from a format preserving document the way they were written, such as
+    /// `0xff` or `1_000`, in the default and toml output. See [crate::eval_document]
+    pub original_integers: bool,
+    /// Escape everything outside of ascii in json output
+    pub ascii_output: bool,
+    pub datetimes: DatetimeFormat,
+    pub delimiter: Delimiter,
+    /// Highlight the default and json output with ANSI colours
+    pub color_output: bool,
+    pub colors: Colors,
+    /// The formatter of the output format, built in or registered
+    formatter: Box<dyn Formatter>,
 }
 
 impl Settings {
-    fn build(raw_output: bool, json_output: bool, compact_output: bool) -> Self {
-        debug!(
-            "raw: {}, json: {}, compact: {}",
-            raw_output, json_output, compact_output
-        );
-        // TODO: There is definitely a better way to enforce this
-        // TODO: at least note this panics in docs
-        assert!(
-            !(raw_output && json_output),
-            "Settings 'raw output' and 'json output' are mutually exclusive"
-        );
-        Self {
-            raw_output,
-            json_output,
+    fn build(builder: SettingsBuilder) -> Result<Self, SettingsError> {
+        let SettingsBuilder {
+            format,
+            compact_output,
+            indent,
+            tab,
+            sort_keys,
+            max_width,
+            original_integers,
+            ascii_output,
+            datetimes,
+            delimiter,
+            color_output,
+            colors,
+            mut formatters,
+        } = builder;
+        debug!("format: {:?}, compact: {}", format, compact_output);
+        let formatter: Box<dyn Formatter> = match &format {
+            OutputFormat::Raw | OutputFormat::Quoted => Box::new(Raw),
+            OutputFormat::Json => Box::new(Json),
+            OutputFormat::Toml => Box::new(Toml),
+            OutputFormat::Yaml => Box::new(Yaml),
+            OutputFormat::Dotenv => Box::new(Dotenv),
+            OutputFormat::Custom(name) => match formatters.remove(name) {
+                Some(formatter) => formatter,
+                None => return Err(SettingsError::UnknownFormat(name.clone())),
+            },
+        };
+        Ok(Self {
+            separator: separator(compact_output, format == OutputFormat::Json),
+            format,
             compact_output,
-            separator: separator(compact_output, json_output),
             spacing: spacing(compact_output),
-        }
+            indent,
+            tab,
+            sort_keys,
+            max_width,
+            original_integers,
+            ascii_output,
+            datetimes,
+            delimiter,
+            color_output,
+            colors,
+            formatter,
+        })
+    }
+
+    /// The formatter of the output format: a built in one, or the one registered for a
+    /// [OutputFormat::Custom] format
+    pub fn formatter(&self) -> &dyn Formatter {
+        self.formatter.as_ref()
     }
 
     pub fn new() -> Self {
@@ -66,26 +181,43 @@ impl Settings {
 
 impl Default for Settings {
     fn default() -> Self {
-        let raw_output = RAW_OUTPUT_DEFAULT;
-        let json_output = JSON_OUTPUT_DEFAULT;
-        let compact_output = COMPACT_OUTPUT_DEFAULT;
-        Settings::build(raw_output, json_output, compact_output)
+        Settings::build(SettingsBuilder::default()).expect("the default format is built in")
     }
 }
 
 #[derive(Debug)]
 pub struct SettingsBuilder {
-    raw_output: bool,
+    format: OutputFormat,
     compact_output: bool,
-    json_output: bool,
+    indent: usize,
+    tab: bool,
+    sort_keys: bool,
+    max_width: Option<usize>,
+    original_integers: bool,
+    ascii_output: bool,
+    datetimes: DatetimeFormat,
+    delimiter: Delimiter,
+    color_output: bool,
+    colors: Colors,
+    formatters: HashMap<String, Box<dyn Formatter>>,
 }
 
 impl Default for SettingsBuilder {
     fn default() -> Self {
         Self {
-            raw_output: RAW_OUTPUT_DEFAULT,
+            format: OutputFormat::default(),
             compact_output: COMPACT_OUTPUT_DEFAULT,
-            json_output: JSON_OUTPUT_DEFAULT,
+            indent: INDENT_DEFAULT,
+            tab: TAB_DEFAULT,
+            sort_keys: SORT_KEYS_DEFAULT,
+            max_width: None,
+            original_integers: ORIGINAL_INTEGERS_DEFAULT,
+            ascii_output: ASCII_OUTPUT_DEFAULT,
+            datetimes: DatetimeFormat::default(),
+            delimiter: Delimiter::default(),
+            color_output: COLOR_OUTPUT_DEFAULT,
+            colors: Colors::default(),
+            formatters: HashMap::new(),
         }
     }
 }
@@ -95,12 +227,17 @@ impl SettingsBuilder {
         Self::default()
     }
 
-    /// Setting this also disables json output
-    pub fn raw_output(mut self, enable: bool) -> Self {
-        self.raw_output = enable;
-        if enable {
-            self.json_output = false;
-        }
+    /// How to write results. Defaults to [OutputFormat::Raw]
+    pub fn output_format(mut self, format: OutputFormat) -> Self {
+        self.format = format;
+        self
+    }
+
+    /// Register a formatter under a name, to be picked with [OutputFormat::Custom]. Registering
+    /// another formatter under the same name replaces it
+    pub fn formatter(mut self, name: &str, formatter: impl Formatter + 'static) -> Self {
+        self.formatters
+            .insert(name.to_string(), Box::new(formatter));
         self
     }
 
@@ -109,16 +246,70 @@ impl SettingsBuilder {
         self
     }
 
-    /// Setting this also disables raw output
-    pub fn json_output(mut self, enable: bool) -> Self {
-        self.json_output = enable;
-        if enable {
-            self.raw_output = false;
-        }
+    /// Spaces per level of indentation when pretty printing
+    pub fn indent(mut self, indent: usize) -> Self {
+        self.indent = indent;
+        self
+    }
+
+    /// Indent with a tab per level instead of spaces
+    pub fn tab(mut self, enable: bool) -> Self {
+        self.tab = enable;
+        self
+    }
+
+    /// Write the keys of every table in sorted order
+    pub fn sort_keys(mut self, enable: bool) -> Self {
+        self.sort_keys = enable;
+        self
+    }
+
+    /// Widest line nested tables and arrays may be written on before they are expanded over
+    /// multiple lines. `None` leaves json and toml output as usual, and the default output at 80
+    pub fn max_width(mut self, width: Option<usize>) -> Self {
+        self.max_width = width;
+        self
+    }
+
+    /// Write integers read from a format preserving document the way they were written, such as
+    /// `0xff` or `1_000`, in the default and toml output
+    pub fn original_integers(mut self, enable: bool) -> Self {
+        self.original_integers = enable;
+        self
+    }
+
+    /// Escape non-ascii characters in json output as `\uXXXX`
+    pub fn ascii_output(mut self, enable: bool) -> Self {
+        self.ascii_output = enable;
+        self
+    }
+
+    /// How to write datetimes in json output
+    pub fn datetimes(mut self, datetimes: DatetimeFormat) -> Self {
+        self.datetimes = datetimes;
+        self
+    }
+
+    /// What to write after each result when a query has several
+    pub fn delimiter(mut self, delimiter: Delimiter) -> Self {
+        self.delimiter = delimiter;
+        self
+    }
+
+    /// Highlight the default and json output with ANSI colours
+    pub fn color_output(mut self, enable: bool) -> Self {
+        self.color_output = enable;
+        self
+    }
+
+    /// Colours used by color output
+    pub fn colors(mut self, colors: Colors) -> Self {
+        self.colors = colors;
         self
     }
 
-    pub fn build(self) -> Settings {
-        Settings::build(self.raw_output, self.json_output, self.compact_output)
+    /// Fails when the output format is a [OutputFormat::Custom] format with no formatter registered
+    pub fn build(self) -> Result<Settings, SettingsError> {
+        Settings::build(self)
     }
 }
@@ -0,0 +1,298 @@
+use crate::edit::value_to_edit_value;
+use crate::format::to_json;
+use data_encoding::{BASE32, BASE64, BASE64_NOPAD};
+use eyre::bail;
+use eyre::Result;
+use toml::{Table, Value};
+
+/// `@text`: strings as they are, other values as inline toml
+pub(crate) fn text(value: &Value) -> String {
+    match value {
+        Value::String(string) => string.clone(),
+        Value::Datetime(date) => date.to_string(),
+        _ => value_to_edit_value(value).to_string(),
+    }
+}
+
+/// `@base64d`: decode base64, with or without padding, as a utf-8 string
+fn base64_decode(value: &Value) -> Result<String> {
+    let encoded = text(value);
+    let Ok(bytes) = BASE64_NOPAD.decode(encoded.trim_end_matches('=').as_bytes()) else {
+        bail!("Cannot decode as base64: {}", encoded);
+    };
+    match String::from_utf8(bytes) {
+        Ok(string) => Ok(string),
+        Err(_) => bail!("Decoded base64 is not utf-8: {}", encoded),
+    }
+}
+
+/// `@uri`: percent encode every byte but the unreserved characters of RFC 3986
+fn uri(value: &Value) -> String {
+    let mut encoded = String::new();
+    for byte in text(value).bytes() {
+        match byte {
+            b'A'..=b'Z' | b'a'..=b'z' | b'0'..=b'9' | b'-' | b'_' | b'.' | b'~' => {
+                encoded.push(byte as char)
+            }
+            _ => encoded.push_str(&format!("%{:02X}", byte)),
+        }
+    }
+    encoded
+}
+
+/// `@html`: escape the characters that are special in html text and attributes
+fn html(value: &Value) -> String {
+    let mut escaped = String::new();
+    for c in text(value).chars() {
+        match c {
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '&' => escaped.push_str("&amp;"),
+            '\'' => escaped.push_str("&#39;"),
+            '"' => escaped.push_str("&quot;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// Quote a string as a single shell word, safe to `eval` whatever it contains
+pub(crate) fn sh_quote(string: &str) -> String {
+    format!("'{}'", string.replace('\'', r"'\''"))
+}
+
+/// A scalar as a shell word. Strings and datetimes are quoted, numbers and booleans are left as is
+pub(crate) fn sh_word(value: &Value) -> Result<String> {
+    match value {
+        Value::String(string) => Ok(sh_quote(string)),
+        Value::Datetime(date) => Ok(sh_quote(&date.to_string())),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Table(_) => {
+            bail!("Cannot quote {} for a shell: {}", value.type_str(), value)
+        }
+    }
+}
+
+/// `@sh`: a scalar as one shell word, or an array of scalars as space separated words
+fn sh(value: &Value) -> Result<String> {
+    match value {
+        Value::Array(array) => {
+            let words: Result<Vec<String>> = array.iter().map(sh_word).collect();
+            Ok(words?.join(" "))
+        }
+        _ => sh_word(value),
+    }
+}
+
+/// A scalar as a csv field. Strings and datetimes are quoted, with quotes doubled
+fn csv_field(value: &Value) -> Result<String> {
+    match value {
+        Value::String(string) => Ok(format!("\"{}\"", string.replace('"', "\"\""))),
+        Value::Datetime(date) => Ok(format!("\"{}\"", date)),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Table(_) => {
+            bail!(
+                "Cannot write {} as a csv field: {}",
+                value.type_str(),
+                value
+            )
+        }
+    }
+}
+
+/// A scalar as a tsv field. Backslashes, tabs and line breaks are escaped as `\\`, `\t`, `\n` and `\r`
+fn tsv_field(value: &Value) -> Result<String> {
+    match value {
+        Value::String(string) => Ok(string
+            .replace('\\', r"\\")
+            .replace('\t', r"\t")
+            .replace('\n', r"\n")
+            .replace('\r', r"\r")),
+        Value::Datetime(date) => Ok(date.to_string()),
+        Value::Integer(_) | Value::Float(_) | Value::Boolean(_) => Ok(value.to_string()),
+        Value::Array(_) | Value::Table(_) => {
+            bail!(
+                "Cannot write {} as a tsv field: {}",
+                value.type_str(),
+                value
+            )
+        }
+    }
+}
+
+/// `@csv` and `@tsv`: an array of scalars as one row, with fields joined by the separator
+fn row(value: &Value, field: fn(&Value) -> Result<String>, separator: &str) -> Result<String> {
+    let Value::Array(array) = value else {
+        bail!(
+            "Only arrays can be written as a row, got {}",
+            value.type_str()
+        );
+    };
+    let fields: Result<Vec<String>> = array.iter().map(field).collect();
+    Ok(fields?.join(separator))
+}
+
+/// The tables of an array of tables, with every key of them in the order they are first seen.
+/// `kind` names what is being written for the error, such as "a csv table"
+pub(crate) fn columns<'a>(
+    value: &'a Value,
+    kind: &str,
+) -> Result<(Vec<&'a String>, Vec<&'a Table>)> {
+    let Some(tables) = value.as_array().and_then(|array| {
+        array
+            .iter()
+            .map(Value::as_table)
+            .collect::<Option<Vec<_>>>()
+    }) else {
+        bail!(
+            "Only arrays of tables can be written as {}, got {}",
+            kind,
+            match value {
+                Value::Array(_) => "an array of other values",
+                _ => value.type_str(),
+            }
+        );
+    };
+    let mut keys: Vec<&String> = Vec::new();
+    for key in tables.iter().flat_map(|table| table.keys()) {
+        if !keys.contains(&key) {
+            keys.push(key);
+        }
+    }
+    Ok((keys, tables))
+}
+
+/// Write an array of tables as csv: a header row with every key, in the order they are first
+/// seen, then a row per table. Keys a table does not have are left empty
+pub fn csv_table(value: &Value) -> Result<String> {
+    let (keys, tables) = columns(value, "a csv table")?;
+    let header: Vec<Value> = keys
+        .iter()
+        .map(|key| Value::String(key.to_string()))
+        .collect();
+    let mut lines = vec![row(&Value::Array(header), csv_field, ",")?];
+    for table in tables {
+        let fields: Result<Vec<String>> = keys
+            .iter()
+            .map(|key| table.get(*key).map_or(Ok(String::new()), csv_field))
+            .collect();
+        lines.push(fields?.join(","));
+    }
+    Ok(lines.join("\n"))
+}
+
+/// Apply a named `@format` to a value, giving a string
+pub(crate) fn apply_format(name: &str, value: &Value) -> Result<String> {
+    let formatted = match name {
+        "text" => text(value),
+        "json" => to_json(value).to_string(),
+        "base64" => BASE64.encode(text(value).as_bytes()),
+        "base64d" => base64_decode(value)?,
+        "base32" => BASE32.encode(text(value).as_bytes()),
+        "uri" => uri(value),
+        "html" => html(value),
+        "sh" => sh(value)?,
+        "csv" => row(value, csv_field, ",")?,
+        "tsv" => row(value, tsv_field, "\t")?,
+        _ => bail!("Unknown format '@{}'", name),
+    };
+    Ok(formatted)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    fn format(name: &str, toml: &str) -> Result<String> {
+        let value: toml::Table = toml::from_str(&format!("v = {}", toml)).unwrap();
+        apply_format(name, &value["v"])
+    }
+
+    #[test]
+    fn test_sh() {
+        assert_eq!("'toml-path'", format("sh", r#""toml-path""#).unwrap());
+        let expected = r#"'it'\''s $HOME
+`ls`'"#;
+        assert_eq!(expected, format("sh", r#""it's $HOME\n`ls`""#).unwrap());
+        assert_eq!("1.5", format("sh", "1.5").unwrap());
+        let expected = "'a b' 1 true '1979-05-27'";
+        assert_eq!(
+            expected,
+            format("sh", r#"["a b", 1, true, 1979-05-27]"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_sh_errors() {
+        assert!(format("sh", "{ a = 1 }").is_err());
+        assert!(format("sh", "[[1]]").is_err());
+        assert!(format("nope", "1").is_err());
+    }
+
+    #[test]
+    fn test_text_json() {
+        assert_eq!("toml-path", format("text", r#""toml-path""#).unwrap());
+        assert_eq!("1979-05-27", format("text", "1979-05-27").unwrap());
+        assert_eq!(
+            "{ a = [1, 2.0] }",
+            format("text", "{ a = [1, 2.0] }").unwrap()
+        );
+        let expected = r#"{"a":"it's \"quoted\""}"#;
+        assert_eq!(
+            expected,
+            format("json", r#"{ a = "it's \"quoted\"" }"#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_encodings() {
+        assert_eq!("dG9tbC1wYXRo", format("base64", r#""toml-path""#).unwrap());
+        assert_eq!("YQ==", format("base64", r#""a""#).unwrap());
+        assert_eq!("toml-path", format("base64d", r#""dG9tbC1wYXRo""#).unwrap());
+        assert_eq!("a", format("base64d", r#""YQ==""#).unwrap());
+        assert_eq!("a", format("base64d", r#""YQ""#).unwrap());
+        assert!(format("base64d", r#""not base64!""#).is_err());
+        assert!(format("base64d", r#""/w==""#).is_err());
+        assert_eq!("MEYQ====", format("base32", r#""a1""#).unwrap());
+        let expected = "caf%C3%A9%20%26%20bar%3F~-_.";
+        assert_eq!(expected, format("uri", r#""café & bar?~-_.""#).unwrap());
+        let expected = "&lt;a href=&quot;x&quot;&gt;it&#39;s &amp;&lt;/a&gt;";
+        assert_eq!(
+            expected,
+            format("html", r#""<a href=\"x\">it's &</a>""#).unwrap()
+        );
+    }
+
+    #[test]
+    fn test_csv_tsv() {
+        let toml = r#"["a,b", "say \"hi\"", 1, 2.5, true, 1979-05-27, "tab\tand\\"]"#;
+        let expected = r#""a,b","say ""hi""",1,2.5,true,"1979-05-27","tab	and\""#;
+        assert_eq!(expected, format("csv", toml).unwrap());
+        let expected = r#"a,b	say "hi"	1	2.5	true	1979-05-27	tab\tand\\"#;
+        assert_eq!(expected, format("tsv", toml).unwrap());
+        assert!(format("csv", r#""a""#).is_err());
+        assert!(format("tsv", "[[1]]").is_err());
+    }
+
+    #[test]
+    fn test_csv_table() {
+        let value: toml::Table = toml::from_str(
+            r#"
+[[server]]
+name = "alpha"
+port = 8080
+
+[[server]]
+name = "beta"
+region = "eu, west"
+"#,
+        )
+        .unwrap();
+        let expected = r#""name","port","region"
+"alpha",8080,
+"beta",,"eu, west""#;
+        assert_eq!(expected, csv_table(&value["server"]).unwrap());
+        assert!(csv_table(&Value::Array(vec![Value::Integer(1)])).is_err());
+    }
+}
@@ -0,0 +1,179 @@
+use crate::string_formats::{columns, text};
+use eyre::Result;
+use toml::Value;
+use unicode_width::{UnicodeWidthChar, UnicodeWidthStr};
+
+/// Space between two columns
+const GAP: &str = "  ";
+
+/// Columns are not narrowed below this, so a truncated cell still shows some of its text
+const MIN_COLUMN_WIDTH: usize = 4;
+
+/// A cell on a single line: strings as they are and other values as inline toml, with line breaks
+/// and tabs escaped
+fn cell(value: &Value) -> String {
+    let mut cell = String::new();
+    for c in text(value).chars() {
+        match c {
+            '\n' => cell.push_str("\\n"),
+            '\r' => cell.push_str("\\r"),
+            '\t' => cell.push_str("\\t"),
+            c => cell.push(c),
+        }
+    }
+    cell
+}
+
+/// Columns text takes up in a terminal, with wide characters, such as most CJK, taking two
+fn width(text: &str) -> usize {
+    UnicodeWidthStr::width(text)
+}
+
+/// Cut text down to a width, ending it with an ellipsis when anything was cut
+fn truncate(text: &str, max_width: usize) -> String {
+    if width(text) <= max_width {
+        return text.to_string();
+    }
+    let mut truncated = String::new();
+    let mut used = 0;
+    for c in text.chars() {
+        used += c.width().unwrap_or(0);
+        if used >= max_width {
+            break;
+        }
+        truncated.push(c);
+    }
+    truncated.push('…');
+    truncated
+}
+
+/// Narrow the widest column until the columns fit in `max_width`, or are all at the minimum width
+fn fit(widths: &mut [usize], max_width: usize) {
+    let gaps = width(GAP) * widths.len().saturating_sub(1);
+    while widths.iter().sum::<usize>() + gaps > max_width {
+        let Some(widest) = widths.iter_mut().max() else {
+            return;
+        };
+        if *widest <= MIN_COLUMN_WIDTH {
+            return;
+        }
+        *widest -= 1;
+    }
+}
+
+fn line(cells: &[String], widths: &[usize]) -> String {
+    let cells: Vec<String> = cells
+        .iter()
+        .zip(widths)
+        .map(|(cell, column)| {
+            // Padded by hand, as format pads to a number of chars rather than columns
+            let cell = truncate(cell, *column);
+            let padding = column.saturating_sub(width(&cell));
+            format!("{}{}", cell, " ".repeat(padding))
+        })
+        .collect();
+    cells.join(GAP).trim_end().to_string()
+}
+
+/// Draw an array of tables as an aligned text table: a row of headers with every key, in the order
+/// they are first seen, a rule, then a row per table. Keys a table does not have are left empty.
+/// Given a maximum width, the widest columns are narrowed until the table fits, and cells that no
+/// longer fit end in `…`
+pub fn text_table(value: &Value, max_width: Option<usize>) -> Result<String> {
+    let (keys, tables) = columns(value, "a text table")?;
+    if keys.is_empty() {
+        return Ok(String::new());
+    }
+    let header: Vec<String> = keys
+        .iter()
+        .map(|key| cell(&Value::String(key.to_string())))
+        .collect();
+    let rows: Vec<Vec<String>> = tables
+        .iter()
+        .map(|table| {
+            keys.iter()
+                .map(|key| table.get(*key).map_or(String::new(), cell))
+                .collect()
+        })
+        .collect();
+    let mut widths: Vec<usize> = header.iter().map(|key| width(key)).collect();
+    for row in &rows {
+        for (column, cell) in widths.iter_mut().zip(row) {
+            *column = (*column).max(width(cell));
+        }
+    }
+    if let Some(max_width) = max_width {
+        fit(&mut widths, max_width);
+    }
+    let rule: Vec<String> = widths.iter().map(|width| "-".repeat(*width)).collect();
+    let lines: Vec<String> = [header, rule]
+        .into_iter()
+        .chain(rows)
+        .map(|row| line(&row, &widths))
+        .collect();
+    Ok(lines.join("\n"))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+
+    const TOML: &str = r#"
+[[server]]
+name = "alpha"
+port = 8080
+tags = ["web", "eu"]
+
+[[server]]
+name = "beta"
+region = "eu-west-1, with a long description"
+"#;
+
+    #[test]
+    fn test_text_table() {
+        let value: toml::Table = toml::from_str(TOML).unwrap();
+        let expected = r#"name   port  tags           region
+-----  ----  -------------  ----------------------------------
+alpha  8080  ["web", "eu"]
+beta                        eu-west-1, with a long description"#;
+        assert_eq!(expected, text_table(&value["server"], None).unwrap());
+        assert!(text_table(&Value::Integer(1), None).is_err());
+        assert_eq!("", text_table(&Value::Array(Vec::new()), None).unwrap());
+    }
+
+    #[test]
+    fn test_text_table_max_width() {
+        let value: toml::Table = toml::from_str(TOML).unwrap();
+        let expected = r#"name   port  tags        region
+-----  ----  ----------  ----------
+alpha  8080  ["web", "…
+beta                     eu-west-1…"#;
+        assert_eq!(expected, text_table(&value["server"], Some(35)).unwrap());
+    }
+
+    #[test]
+    fn test_text_table_wide_characters() {
+        let toml = r#"
+[[city]]
+name = "東京"
+note = "首都で人口が最も多い"
+
+[[city]]
+name = "Zürich"
+note = "on the lake"
+"#;
+        let value: toml::Table = toml::from_str(toml).unwrap();
+        // Wide characters take two columns, and are left out whole when only one column is left
+        let expected = r#"name    note
+------  -----------
+東京    首都で人口…
+Zürich  on the lake"#;
+        assert_eq!(expected, text_table(&value["city"], Some(19)).unwrap());
+        let expected = r#"name    note
+------  ----------
+東京    首都で人…
+Zürich  on the la…"#;
+        assert_eq!(expected, text_table(&value["city"], Some(18)).unwrap());
+    }
+}
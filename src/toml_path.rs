@@ -1,13 +1,19 @@
 use eyre::Result;
+use serde::de;
+use serde::{Deserialize, Deserializer};
+use std::fmt;
 use std::str::FromStr;
 use thiserror::Error;
 use winnow::combinator::repeat;
 use winnow::prelude::*;
 
 mod op;
+use op::is_bare_key;
 use op::op;
 pub use op::Index;
 pub use op::Op;
+pub(crate) use op::Range;
+pub(crate) use op::{escape, escaped};
 
 /// TODO: doc comments
 /// Impls std::str::FromStr for convenience
@@ -20,13 +26,63 @@ impl TomlPath {
     pub fn parts(&self) -> &[Op] {
         &self.parts
     }
+
+    /// Path to the root of a toml, "."
+    pub(crate) fn root() -> Self {
+        Self {
+            parts: vec![Op::Dot],
+        }
+    }
+
+    fn is_root(&self) -> bool {
+        self.parts == [Op::Dot]
+    }
+
+    /// Path to the child at `key` of the table at this path
+    pub(crate) fn join_key(&self, key: &str) -> Self {
+        let mut parts = self.parts.clone();
+        if is_bare_key(key) {
+            if !self.is_root() {
+                parts.push(Op::Dot);
+            }
+            parts.push(Op::Name(key.to_string()));
+        } else {
+            parts.push(Op::BracketName(vec![key.to_string()]));
+        }
+        Self { parts }
+    }
+
+    /// Path to the item at `index` of the array at this path
+    pub(crate) fn join_index(&self, index: usize) -> Self {
+        let mut parts = self.parts.clone();
+        parts.push(Op::BracketIndex(vec![Index::Number(index as isize)]));
+        Self { parts }
+    }
+
+    /// Path to the slice from `start` up to `end` of the array at this path
+    pub(crate) fn join_range(&self, start: usize, end: usize) -> Self {
+        let mut parts = self.parts.clone();
+        let range = Range::new(start as isize, end as isize);
+        parts.push(Op::BracketIndex(vec![Index::Range(range)]));
+        Self { parts }
+    }
+}
+
+/// Writes the path in toml-path syntax, such that it can be parsed back with [TomlPath::from_str]
+impl fmt::Display for TomlPath {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        for part in &self.parts {
+            write!(f, "{}", part)?;
+        }
+        Ok(())
+    }
 }
 
 fn path_parts(s: &mut &str) -> PResult<Vec<Op>> {
     repeat(1.., op).parse_next(s)
 }
 
-fn toml_path(s: &mut &str) -> PResult<TomlPath> {
+pub(crate) fn toml_path(s: &mut &str) -> PResult<TomlPath> {
     path_parts.map(|parts| TomlPath { parts }).parse_next(s)
 }
 
@@ -44,6 +100,15 @@ impl FromStr for TomlPath {
     }
 }
 
+/// Deserializes from a string in toml-path syntax
+impl<'de> Deserialize<'de> for TomlPath {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let s = String::deserialize(deserializer)?;
+        TomlPath::from_str(&s)
+            .map_err(|_| de::Error::custom(format!("Unable to parse toml path '{}'", s)))
+    }
+}
+
 #[cfg(test)]
 mod tests {
     use super::*;
@@ -147,6 +212,65 @@ mod tests {
         assert_eq!(expected, result);
     }
 
+    #[test]
+    fn test_toml_path_dot_names_with_dashes() {
+        let input = ".dev-dependencies.toml_edit";
+        let expected = Ok(TomlPath {
+            parts: vec![
+                Op::Dot,
+                Op::Name(String::from("dev-dependencies")),
+                Op::Dot,
+                Op::Name(String::from("toml_edit")),
+            ],
+        });
+        let result = TomlPath::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_toml_path_dot_bracket_name_escaped() {
+        let input = r#".["docs.rs", "a \"quoted\" key"]"#;
+        let expected = Ok(TomlPath {
+            parts: vec![
+                Op::Dot,
+                Op::BracketName(vec![
+                    String::from("docs.rs"),
+                    String::from("a \"quoted\" key"),
+                ]),
+            ],
+        });
+        let result = TomlPath::from_str(input);
+        assert_eq!(expected, result);
+    }
+
+    #[test]
+    fn test_toml_path_display() {
+        let inputs = [
+            ".",
+            ".foo.bar",
+            ".foo[0]",
+            ".[1, -2:4]",
+            r#".package["name", "version"]"#,
+            r#".["docs.rs"]"#,
+        ];
+        for input in inputs {
+            let path = TomlPath::from_str(input).unwrap();
+            assert_eq!(input, path.to_string());
+        }
+    }
+
+    #[test]
+    fn test_toml_path_join() {
+        let path = TomlPath::root()
+            .join_key("package")
+            .join_key("metadata")
+            .join_key("docs.rs")
+            .join_index(0);
+        assert_eq!(r#".package.metadata["docs.rs"][0]"#, path.to_string());
+        assert_eq!(Ok(path.clone()), TomlPath::from_str(&path.to_string()));
+        assert_eq!(".[0]", TomlPath::root().join_index(0).to_string());
+    }
+
     #[test]
     fn test_toml_path_dot_bracket_number() {
         let input = ".[1]";
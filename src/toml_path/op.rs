@@ -1,15 +1,18 @@
 use eyre::bail;
 use eyre::Result;
 use std::fmt;
-use winnow::ascii::alphanumeric1;
 use winnow::ascii::dec_int;
 use winnow::ascii::space0;
 use winnow::combinator::alt;
 use winnow::combinator::delimited;
 use winnow::combinator::opt;
+use winnow::combinator::preceded;
+use winnow::combinator::repeat;
 use winnow::combinator::separated;
 use winnow::combinator::separated_pair;
 use winnow::prelude::*;
+use winnow::token::none_of;
+use winnow::token::take_while;
 
 // TODO: add winnnow contexts for better error messages to all parsers
 
@@ -90,6 +93,12 @@ impl Range {
     }
 }
 
+impl fmt::Display for Range {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}:{}", self.start, self.end)
+    }
+}
+
 fn number(s: &mut &str) -> PResult<isize> {
     dec_int.parse_next(s)
 }
@@ -100,6 +109,15 @@ pub enum Index {
     Range(Range),
 }
 
+impl fmt::Display for Index {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            Index::Number(number) => write!(f, "{}", number),
+            Index::Range(range) => write!(f, "{}", range),
+        }
+    }
+}
+
 fn range(s: &mut &str) -> PResult<Range> {
     separated_pair(number, space_colon_space, number)
         .map(|(start, end)| Range { start, end })
@@ -107,11 +125,11 @@ fn range(s: &mut &str) -> PResult<Range> {
 }
 
 fn index_range(s: &mut &str) -> PResult<Index> {
-    range.map(|r| Index::Range(r)).parse_next(s)
+    range.map(Index::Range).parse_next(s)
 }
 
 fn index_number(s: &mut &str) -> PResult<Index> {
-    number.map(|x| Index::Number(x)).parse_next(s)
+    number.map(Index::Number).parse_next(s)
 }
 
 fn index(s: &mut &str) -> PResult<Index> {
@@ -138,19 +156,50 @@ pub enum Op {
     BracketName(Vec<String>),
 }
 
+/// Keys made up of these characters may be written without quotes, like bare keys in toml
+fn is_bare_key_char(c: char) -> bool {
+    c.is_ascii_alphanumeric() || c == '_' || c == '-'
+}
+
+pub(crate) fn is_bare_key(key: &str) -> bool {
+    !key.is_empty() && key.chars().all(is_bare_key_char)
+}
+
+/// Surround with double quotes, escaping characters as needed
+fn quote(s: &str) -> String {
+    format!("\"{}\"", escaped(s))
+}
+
+/// Escape characters as needed inside of double quotes
+pub(crate) fn escaped(s: &str) -> String {
+    let mut quoted = String::new();
+    for c in s.chars() {
+        match c {
+            '"' => quoted.push_str("\\\""),
+            '\\' => quoted.push_str("\\\\"),
+            '\n' => quoted.push_str("\\n"),
+            '\r' => quoted.push_str("\\r"),
+            '\t' => quoted.push_str("\\t"),
+            c if c.is_control() => quoted.push_str(&format!("\\u{:04X}", c as u32)),
+            c => quoted.push(c),
+        }
+    }
+    quoted
+}
+
+fn join<T>(items: &[T], f: impl Fn(&T) -> String) -> String {
+    items.iter().map(f).collect::<Vec<_>>().join(", ")
+}
+
+/// Writes the op in toml-path syntax
 impl fmt::Display for Op {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
         let repr = match self {
             Op::Dot => String::from("."),
-            Op::Name(string) => {
-                format!("Op::Name({:?})", string)
-            }
-            Op::BracketIndex(indexes) => {
-                format!("Op::BracketIndex({:?})", indexes)
-            }
-            Op::BracketName(names) => {
-                format!("Op::BracketName({:?})", names)
-            }
+            Op::Name(name) if is_bare_key(name) => name.clone(),
+            Op::Name(name) => format!("[{}]", quote(name)),
+            Op::BracketIndex(indexes) => format!("[{}]", join(indexes, |i| i.to_string())),
+            Op::BracketName(names) => format!("[{}]", join(names, |n| quote(n))),
         };
 
         write!(f, "{}", repr)
@@ -162,15 +211,44 @@ fn dot(s: &mut &str) -> PResult<Op> {
 }
 
 fn name_str(s: &mut &str) -> PResult<String> {
-    alphanumeric1.map(|s: &str| s.to_string()).parse_next(s)
+    take_while(1.., is_bare_key_char)
+        .map(|s: &str| s.to_string())
+        .parse_next(s)
 }
 
 fn name(s: &mut &str) -> PResult<Op> {
-    name_str.map(|name| Op::Name(name)).parse_next(s)
+    name_str.map(Op::Name).parse_next(s)
+}
+
+fn unicode_escape(s: &mut &str) -> PResult<char> {
+    preceded('u', take_while(4, |c: char| c.is_ascii_hexdigit()))
+        .try_map(|hex| u32::from_str_radix(hex, 16))
+        .verify_map(char::from_u32)
+        .parse_next(s)
+}
+
+pub(crate) fn escape(s: &mut &str) -> PResult<char> {
+    preceded(
+        '\\',
+        alt((
+            '"'.value('"'),
+            '\\'.value('\\'),
+            'n'.value('\n'),
+            'r'.value('\r'),
+            't'.value('\t'),
+            unicode_escape,
+        )),
+    )
+    .parse_next(s)
+}
+
+/// Double quoted string, with backslash escapes
+fn quoted_str(s: &mut &str) -> PResult<String> {
+    delimited('"', repeat(0.., alt((none_of(['"', '\\']), escape))), '"').parse_next(s)
 }
 
 fn quoted_name(s: &mut &str) -> PResult<String> {
-    delimited("\"", name_str, "\"").parse_next(s)
+    quoted_str.parse_next(s)
 }
 
 fn comma_space(s: &mut &str) -> PResult<()> {
@@ -188,7 +266,7 @@ fn space_colon_space(s: &mut &str) -> PResult<()> {
 
 fn bracket_name_list(s: &mut &str) -> PResult<Op> {
     let list = separated(1.., quoted_name, comma_space)
-        .map(|list| Op::BracketName(list))
+        .map(Op::BracketName)
         .parse_next(s)?;
     // Ignore possible trailing comma and space
     let _ = opt(comma_space).parse_next(s)?;
@@ -197,7 +275,7 @@ fn bracket_name_list(s: &mut &str) -> PResult<Op> {
 
 fn bracket_index_list(s: &mut &str) -> PResult<Op> {
     let list = separated(1.., index, comma_space)
-        .map(|list| Op::BracketIndex(list))
+        .map(Op::BracketIndex)
         .parse_next(s)?;
     // Ignore possible trailing comma and space
     let _ = opt(comma_space).parse_next(s)?;
@@ -1,153 +1,297 @@
-use crate::toml_path::{Index, Op};
-use eyre::bail;
+use crate::toml_path::{Index, Op, Range, TomlPath};
 use eyre::Result;
+use std::borrow::Cow;
+use thiserror::Error;
 use toml::Value;
 
-pub fn traverse(value: &Value, path: &[Op]) -> Result<Value> {
-    let current_op = &path[0];
-    let num_ops = path.len();
-    match value {
-        Value::String(string) => {
-            if num_ops > 1 {
-                bail!(
-                    "Hit the end of toml tree (string: '{}') but path has more parts left: {:?}",
-                    string,
-                    path[1..].to_vec()
-                );
-            }
-            return Ok(value.clone());
+/// What a node of a tree holds, as far as a path cares
+pub(crate) enum Kind {
+    Table,
+    /// An array with this many items
+    Array(usize),
+    /// Anything else, by its toml type, such as `string`
+    Other(&'static str),
+}
+
+/// A tree a toml path can be resolved in, such as a toml value, or a parsed document that knows
+/// where each of its values was written
+pub(crate) trait Tree: Sized + Clone {
+    fn kind(&self) -> Kind;
+    /// The child at a key of a table
+    fn key(&self, key: &str) -> Option<Self>;
+    /// The item at an index of an array
+    fn index(&self, index: usize) -> Option<Self>;
+    /// The items at some indexes of an array, as one array
+    fn slice(&self, indexes: &[usize]) -> Self;
+}
+
+/// Values are borrowed from the toml, apart from slices, which are new arrays
+impl Tree for Cow<'_, Value> {
+    fn kind(&self) -> Kind {
+        match self.as_ref() {
+            Value::Table(_) => Kind::Table,
+            Value::Array(array) => Kind::Array(array.len()),
+            _ => Kind::Other(self.type_str()),
         }
-        Value::Integer(int) => {
-            if num_ops > 1 {
-                bail!(
-                    "Hit the end of toml tree (integer: {}) but path has more parts left: {:?}",
-                    int,
-                    path[1..].to_vec()
-                );
-            }
-            return Ok(value.clone());
+    }
+
+    fn key(&self, key: &str) -> Option<Self> {
+        match self {
+            Cow::Borrowed(value) => value.as_table()?.get(key).map(Cow::Borrowed),
+            Cow::Owned(value) => value.as_table()?.get(key).cloned().map(Cow::Owned),
         }
-        Value::Float(float) => {
-            if num_ops > 1 {
-                bail!(
-                    "Hit the end of toml tree (float: {}) but path has more parts left: {:?}",
-                    float,
-                    path[1..].to_vec()
-                );
-            }
-            return Ok(value.clone());
+    }
+
+    fn index(&self, index: usize) -> Option<Self> {
+        match self {
+            Cow::Borrowed(value) => value.as_array()?.get(index).map(Cow::Borrowed),
+            Cow::Owned(value) => value.as_array()?.get(index).cloned().map(Cow::Owned),
         }
-        Value::Boolean(bool) => {
-            if num_ops > 1 {
-                bail!(
-                    "Hit the end of toml tree (bool: {}) but path has more parts left: {:?}",
-                    bool,
-                    path[1..].to_vec()
-                );
-            }
-            return Ok(value.clone());
+    }
+
+    fn slice(&self, indexes: &[usize]) -> Self {
+        let items = indexes
+            .iter()
+            .filter_map(|i| self.as_array()?.get(*i).cloned())
+            .collect();
+        Cow::Owned(Value::Array(items))
+    }
+}
+
+#[derive(Error, Debug, PartialEq, Eq)]
+pub(crate) enum PathError {
+    #[error("Could not find key '{key}' in '{path}'")]
+    MissingKey { key: String, path: String },
+    #[error("No item at index {index} in '{path}'")]
+    MissingIndex { index: isize, path: String },
+    #[error("Cannot index '{path}' with key '{key}', it is {kind}, not a table")]
+    NotTable {
+        key: String,
+        path: String,
+        kind: String,
+    },
+    #[error("Cannot index '{path}' with indexes, it is {kind}, not an array")]
+    NotArray { path: String, kind: String },
+}
+
+/// A kind for error messages, such as `an integer`
+fn kind_name(kind: Kind) -> String {
+    let name = match kind {
+        Kind::Table => "table",
+        Kind::Array(_) => "array",
+        Kind::Other(name) => name,
+    };
+    let article = if name.starts_with(['a', 'e', 'i', 'o', 'u']) {
+        "an"
+    } else {
+        "a"
+    };
+    format!("{} {}", article, name)
+}
+
+fn by_key<T: Tree>(node: &T, path: &TomlPath, key: &str) -> Result<(TomlPath, T)> {
+    if let kind @ (Kind::Array(_) | Kind::Other(_)) = node.kind() {
+        return Err(PathError::NotTable {
+            key: key.to_string(),
+            path: path.to_string(),
+            kind: kind_name(kind),
         }
-        Value::Datetime(date) => {
-            if num_ops > 1 {
-                bail!(
-                    "Hit the end of toml tree (datetime: '{}') but path has more parts left: {:?}",
-                    date,
-                    path[1..].to_vec()
-                );
-            }
-            return Ok(value.clone());
+        .into());
+    }
+    let Some(child) = node.key(key) else {
+        return Err(PathError::MissingKey {
+            key: key.to_string(),
+            path: path.to_string(),
+        }
+        .into());
+    };
+    Ok((path.join_key(key), child))
+}
+
+fn array_len<T: Tree>(node: &T, path: &TomlPath) -> Result<usize> {
+    match node.kind() {
+        Kind::Array(len) => Ok(len),
+        kind => Err(PathError::NotArray {
+            path: path.to_string(),
+            kind: kind_name(kind),
         }
-        Value::Array(array) => match current_op {
-            Op::Dot => {
-                if num_ops == 1 {
-                    return Ok(value.clone());
+        .into()),
+    }
+}
+
+/// A single range is a slice of the array, such as `.[1:3]`, leaving out indexes past its end
+fn by_range<T: Tree>(node: &T, path: &TomlPath, range: &Range) -> Result<(TomlPath, T)> {
+    let len = array_len(node, path)?;
+    let mut indexes = range.gen_range_indexes(len)?;
+    indexes.retain(|i| *i < len);
+    let start = indexes.first().copied().unwrap_or(len);
+    let end = indexes.last().map_or(start, |i| i + 1);
+    Ok((path.join_range(start, end), node.slice(&indexes)))
+}
+
+fn by_indexes<T: Tree>(node: &T, path: &TomlPath, indexes: &[Index]) -> Result<Vec<(TomlPath, T)>> {
+    let len = array_len(node, path)?;
+    let mut found = Vec::new();
+    for index in indexes {
+        let selected: Vec<(isize, Option<usize>)> = match index {
+            Index::Number(i) if *i < 0 => vec![(*i, len.checked_sub(i.unsigned_abs()))],
+            Index::Number(i) => vec![(*i, Some(*i as usize))],
+            Index::Range(range) => range
+                .gen_range_indexes(len)?
+                .into_iter()
+                .map(|i| (i as isize, Some(i)))
+                .collect(),
+        };
+        for (index, i) in selected {
+            let Some((i, item)) = i.and_then(|i| Some((i, node.index(i)?))) else {
+                return Err(PathError::MissingIndex {
+                    index,
+                    path: path.to_string(),
                 }
-                return traverse(&value, &path[1..]);
-            }
-            Op::Name(name) => {
-                bail!("Cannot index array with string ({:?})", name);
-            }
-            Op::BracketIndex(indexes) => {
-                let num_items = array.len();
-                let mut filtered_values: Vec<Value> = Vec::new();
-                for index in indexes {
-                    match index {
-                        Index::Number(i_signed) => {
-                            let i_unsigned = if *i_signed < 1 {
-                                (num_items as isize + i_signed) as usize
-                            } else {
-                                *i_signed as usize
-                            };
-                            let Some(item) = array.get(i_unsigned) else {
-                                bail!("No item at index {} in array ({:?})", i_unsigned, array);
-                            };
-                            filtered_values.push(item.clone());
-                        }
-                        Index::Range(range) => {
-                            for i in range.gen_range_indexes(num_items)? {
-                                let Some(item): Option<&Value> = array.get(i) else {
-                                    bail!(
-                                        "No item at index {} (from range {:?}) in array ({:?})",
-                                        i,
-                                        range,
-                                        array
-                                    );
-                                };
-                                filtered_values.push(item.clone());
-                            }
-                        }
+                .into());
+            };
+            found.push((path.join_index(i), item));
+        }
+    }
+    Ok(found)
+}
+
+/// Every node a path points to, each with the path to that single node, such as `.bin[1].name`.
+/// Several keys or indexes, such as `.bin[0, 1]`, give one result for each, and the rest of the
+/// path is applied to each of them. A single range, such as `.[1:3]`, is a slice, giving one array,
+/// while a range next to other indexes is short for listing its indexes
+pub(crate) fn resolve<T: Tree>(root: T, path: &TomlPath) -> Result<Vec<(TomlPath, T)>> {
+    let mut found = vec![(TomlPath::root(), root)];
+    for op in path.parts() {
+        let mut next = Vec::new();
+        for (path, node) in found {
+            match op {
+                Op::Dot => next.push((path, node)),
+                Op::Name(name) => next.push(by_key(&node, &path, name)?),
+                Op::BracketName(names) => {
+                    for name in names {
+                        next.push(by_key(&node, &path, name)?);
                     }
                 }
-                let subset = Value::Array(filtered_values);
-                if num_ops == 1 {
-                    return Ok(subset);
-                }
-                return traverse(&subset, &path[1..]);
-            }
-            Op::BracketName(names) => {
-                bail!("Cannot index array with strings ({:?})", names);
-            }
-        },
-        Value::Table(table) => match current_op {
-            Op::Dot => {
-                if num_ops == 1 {
-                    return Ok(value.clone());
-                }
-                return traverse(&value, &path[1..]);
-            }
-            Op::Name(name) => {
-                let Some(section) = table.get(name) else {
-                    bail!("Could not find key '{:?}' in table ({:?})", name, table);
-                };
-                if num_ops == 1 {
-                    return Ok(section.clone());
-                }
-                return traverse(section, &path[1..]);
+                Op::BracketIndex(indexes) => match indexes.as_slice() {
+                    [Index::Range(range)] => next.push(by_range(&node, &path, range)?),
+                    indexes => next.extend(by_indexes(&node, &path, indexes)?),
+                },
             }
-            Op::BracketIndex(indexes) => {
-                bail!("Cannot index table with indexes ({:?})", indexes)
-            }
-            Op::BracketName(names) => {
-                let mut filtered_values: Vec<Value> = Vec::new();
-
-                for name in names {
-                    let Some(section) = table.get(name) else {
-                        bail!(
-                            "Could not find key '{:?}' (from keys ({:?}) in table ({:?})",
-                            name,
-                            names,
-                            table
-                        );
-                    };
-                    filtered_values.push(section.clone());
-                }
+        }
+        found = next;
+    }
+    Ok(found)
+}
 
-                let subset = Value::Array(filtered_values);
-                if num_ops == 1 {
-                    return Ok(subset);
-                }
-                return traverse(&subset, &path[1..]);
-            }
-        },
+/// Every value a path points to, as a stream of results. See [resolve]
+pub fn traverse(value: &Value, path: &TomlPath) -> Result<Vec<Value>> {
+    Ok(resolve(Cow::Borrowed(value), path)?
+        .into_iter()
+        .map(|(_, value)| value.into_owned())
+        .collect())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use pretty_assertions::assert_eq;
+    use std::str::FromStr;
+
+    const TOML: &str = r#"
+[[bin]]
+name = "cli"
+
+[[bin]]
+name = "daemon"
+
+[[bin]]
+name = "worker"
+"#;
+
+    fn results(path: &str) -> Result<Vec<Value>> {
+        let value: Value = toml::from_str(TOML).unwrap();
+        traverse(&value, &TomlPath::from_str(path).unwrap())
+    }
+
+    fn names(names: &[&str]) -> Vec<Value> {
+        names.iter().map(|name| Value::from(*name)).collect()
+    }
+
+    fn error(path: &str) -> PathError {
+        results(path).unwrap_err().downcast().unwrap()
+    }
+
+    #[test]
+    fn test_traverse_stream() {
+        assert_eq!(
+            names(&["cli", "worker"]),
+            results(".bin[0, 2].name").unwrap()
+        );
+        assert_eq!(
+            names(&["daemon", "worker", "worker"]),
+            results(".bin[1:3, -1].name").unwrap()
+        );
+        assert_eq!(names(&["worker"]), results(".bin[-1].name").unwrap());
+    }
+
+    #[test]
+    fn test_traverse_slice() {
+        let slice = results(".bin[1:3]").unwrap();
+        assert_eq!(1, slice.len());
+        assert_eq!(Some(2), slice[0].as_array().map(Vec::len));
+        assert_eq!(
+            vec![Value::from("daemon")],
+            results(".bin[1:3][0].name").unwrap()
+        );
+        assert!(results(".bin[0:2].name").is_err());
+    }
+
+    #[test]
+    fn test_resolve_paths() {
+        let value: Value = toml::from_str(TOML).unwrap();
+        let paths = |path: &str| -> Vec<String> {
+            let path = TomlPath::from_str(path).unwrap();
+            resolve(Cow::Borrowed(&value), &path)
+                .unwrap()
+                .into_iter()
+                .map(|(path, _)| path.to_string())
+                .collect()
+        };
+        assert_eq!(
+            vec![".bin[2].name", ".bin[0].name"],
+            paths(".bin[-1, 0:1].name")
+        );
+        assert_eq!(vec![".bin[1:3]"], paths(".bin[-2:3]"));
+    }
+
+    #[test]
+    fn test_traverse_errors() {
+        let missing = PathError::MissingIndex {
+            index: 3,
+            path: String::from(".bin"),
+        };
+        assert_eq!(missing, error(".bin[3].name"));
+        let missing = PathError::MissingIndex {
+            index: -4,
+            path: String::from(".bin"),
+        };
+        assert_eq!(missing, error(".bin[-4].name"));
+        let missing = PathError::MissingKey {
+            key: String::from("path"),
+            path: String::from(".bin[0]"),
+        };
+        assert_eq!(missing, error(".bin[0].path"));
+        let not_table = PathError::NotTable {
+            key: String::from("name"),
+            path: String::from(".bin"),
+            kind: String::from("an array"),
+        };
+        assert_eq!(not_table, error(".bin.name"));
+        let not_array = PathError::NotArray {
+            path: String::from(".bin[0].name"),
+            kind: String::from("a string"),
+        };
+        assert_eq!(not_array, error(".bin[0].name[0]"));
     }
 }
@@ -4,4 +4,11 @@ mod tests {
     fn test_readme_cli_examples() {
         trycmd::TestCases::new().case("README.md");
     }
+
+    #[test]
+    fn test_cli_cases() {
+        trycmd::TestCases::new()
+            .case("tests/cmd/*.trycmd")
+            .case("tests/cmd/*.toml");
+    }
 }